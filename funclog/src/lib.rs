@@ -23,16 +23,17 @@ pub fn funclog(args: TokenStream, item: TokenStream) -> TokenStream {
         let func_name = &ast.sig.ident;
         let func_body = ast.block.as_ref();
         let value_check_block = value_check_block(args);
+        let metadata = metadata();
 
         let block = parse_quote! {{
-            eprintln!("[DEBUG:func_enter({}:{})] {}", file!(), line!(), stringify!(#func_name));
+            eprintln!("[DEBUG:func_enter({}:{}){}] {}", file!(), line!(), #metadata, stringify!(#func_name));
             #value_check_block;
 
             let mut func_body_closure = || #func_body;
             let ret = func_body_closure();
 
             #value_check_block;
-            eprintln!("[DEBUG:func_exit({}:{})] {}", file!(), line!(), stringify!(#func_name));
+            eprintln!("[DEBUG:func_exit({}:{}){}] {}", file!(), line!(), #metadata, stringify!(#func_name));
 
             ret
         }};
@@ -55,6 +56,7 @@ pub fn methodlog(args: TokenStream, item: TokenStream) -> TokenStream {
         let func_name = &ast.sig.ident;
         let func_body = ast.block.as_ref();
         let value_check_block = value_check_block(args);
+        let metadata = metadata();
 
         let block;
 
@@ -63,14 +65,14 @@ pub fn methodlog(args: TokenStream, item: TokenStream) -> TokenStream {
             block = parse_quote! {{
                 use ::proctrack::typename::TypeName;
 
-                eprintln!("[DEBUG:func_enter({}:{})] {}::{}", file!(), line!(), <Self as TypeName>::type_name(self), stringify!(#func_name));
+                eprintln!("[DEBUG:func_enter({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeName>::type_name(self), stringify!(#func_name));
                 #value_check_block;
 
                 let mut func_body_closure = || #func_body;
                 let ret = func_body_closure();
 
                 #value_check_block;
-                eprintln!("[DEBUG:func_exit({}:{})] {}::{}", file!(), line!(), <Self as TypeName>::type_name(self), stringify!(#func_name));
+                eprintln!("[DEBUG:func_exit({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeName>::type_name(self), stringify!(#func_name));
 
                 ret
             }};
@@ -80,14 +82,14 @@ pub fn methodlog(args: TokenStream, item: TokenStream) -> TokenStream {
             block = parse_quote! {{
                 use ::typename::TypeName;
 
-                eprintln!("[DEBUG:func_enter({}:{})] {}::{}", file!(), line!(), <Self as TypeName>::type_name(self), stringify!(#func_name));
+                eprintln!("[DEBUG:func_enter({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeName>::type_name(self), stringify!(#func_name));
                 #value_check_block;
 
                 let mut func_body_closure = || #func_body;
                 let ret = func_body_closure();
 
                 #value_check_block;
-                eprintln!("[DEBUG:func_exit({}:{})] {}::{}", file!(), line!(), <Self as TypeName>::type_name(self), stringify!(#func_name));
+                eprintln!("[DEBUG:func_exit({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeName>::type_name(self), stringify!(#func_name));
 
                 ret
             }};
@@ -111,6 +113,7 @@ pub fn methodlog_move(args: TokenStream, item: TokenStream) -> TokenStream {
         let func_name = &ast.sig.ident;
         let func_body = ast.block.as_ref();
         let value_check_block = value_check_block(args);
+        let metadata = metadata();
 
         let block;
 
@@ -121,14 +124,14 @@ pub fn methodlog_move(args: TokenStream, item: TokenStream) -> TokenStream {
 
                 let typename = <Self as TypeName>::type_name(&self).to_owned();
 
-                eprintln!("[DEBUG:func_enter({}:{})] {}::{}", file!(), line!(), typename, stringify!(#func_name));
+                eprintln!("[DEBUG:func_enter({}:{}){}] {}::{}", file!(), line!(), #metadata, typename, stringify!(#func_name));
                 #value_check_block;
 
                 let mut func_body_closure = || #func_body;
                 let ret = func_body_closure();
 
                 #value_check_block;
-                eprintln!("[DEBUG:func_exit({}:{})] {}::{}", file!(), line!(), typename, stringify!(#func_name));
+                eprintln!("[DEBUG:func_exit({}:{}){}] {}::{}", file!(), line!(), #metadata, typename, stringify!(#func_name));
 
                 ret
             }};
@@ -140,14 +143,14 @@ pub fn methodlog_move(args: TokenStream, item: TokenStream) -> TokenStream {
 
                 let typename = <Self as TypeName>::type_name(&self).to_owned();
 
-                eprintln!("[DEBUG:func_enter({}:{})] {}::{}", file!(), line!(), typename, stringify!(#func_name));
+                eprintln!("[DEBUG:func_enter({}:{}){}] {}::{}", file!(), line!(), #metadata, typename, stringify!(#func_name));
                 #value_check_block;
 
                 let mut func_body_closure = || #func_body;
                 let ret = func_body_closure();
 
                 #value_check_block;
-                eprintln!("[DEBUG:func_exit({}:{})] {}::{}", file!(), line!(), typename, stringify!(#func_name));
+                eprintln!("[DEBUG:func_exit({}:{}){}] {}::{}", file!(), line!(), #metadata, typename, stringify!(#func_name));
 
                 ret
             }};
@@ -171,6 +174,7 @@ pub fn methodlog_static(args: TokenStream, item: TokenStream) -> TokenStream {
         let func_name = &ast.sig.ident;
         let func_body = ast.block.as_ref();
         let value_check_block = value_check_block(args);
+        let metadata = metadata();
 
         let block;
 
@@ -179,14 +183,14 @@ pub fn methodlog_static(args: TokenStream, item: TokenStream) -> TokenStream {
             block = parse_quote! {{
                 use ::proctrack::typename::TypeNameStatic;
 
-                eprintln!("[DEBUG:func_enter({}:{})] {}::{}", file!(), line!(), <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
+                eprintln!("[DEBUG:func_enter({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
                 #value_check_block;
 
                 let mut func_body_closure = || #func_body;
                 let ret = func_body_closure();
 
                 #value_check_block;
-                eprintln!("[DEBUG:func_exit({}:{})] {}::{}", file!(), line!(), <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
+                eprintln!("[DEBUG:func_exit({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
 
                 ret
             }};
@@ -196,14 +200,14 @@ pub fn methodlog_static(args: TokenStream, item: TokenStream) -> TokenStream {
             block = parse_quote! {{
                 use ::typename::TypeNameStatic;
 
-                eprintln!("[DEBUG:func_enter({}:{})] {}::{}", file!(), line!(), <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
+                eprintln!("[DEBUG:func_enter({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
                 #value_check_block;
 
                 let mut func_body_closure = || #func_body;
                 let ret = func_body_closure();
 
                 #value_check_block;
-                eprintln!("[DEBUG:func_exit({}:{})] {}::{}", file!(), line!(), <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
+                eprintln!("[DEBUG:func_exit({}:{}){}] {}::{}", file!(), line!(), #metadata, <Self as TypeNameStatic>::type_name_static(), stringify!(#func_name));
 
                 ret
             }};
@@ -219,6 +223,28 @@ pub fn methodlog_static(args: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
+/// Expression of metadata written after the location of `func_enter` and `func_exit`.
+#[cfg(all(debug_assertions, not(feature = "disable")))]
+fn metadata() -> Expr {
+    #[cfg(feature = "timestamp")]
+    {
+        parse_quote! {
+            format!(
+                " time={} thread={:?}",
+                ::std::time::SystemTime::now()
+                    .duration_since(::std::time::UNIX_EPOCH)
+                    .map(|time| format!("{}.{:09}", time.as_secs(), time.subsec_nanos()))
+                    .unwrap_or_default(),
+                ::std::thread::current().id()
+            )
+        }
+    }
+    #[cfg(not(feature = "timestamp"))]
+    {
+        parse_quote! { "" }
+    }
+}
+
 #[cfg(all(debug_assertions, not(feature = "disable")))]
 fn value_check_block(args: TokenStream) -> Block {
     let args = Punctuated::<Expr, Token![,]>::parse_terminated
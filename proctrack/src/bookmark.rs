@@ -0,0 +1,239 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::visibility_info;
+use anyhow::{Context, Result};
+use proctrack::funclog::methodlog;
+use proctrack::typename_derive::TypeName;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+/// Bookmarked line of debug information.
+///
+/// The text of the line is saved together with its index
+/// so that the bookmark can be found again after debug information is rewritten.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize, TypeName)]
+pub struct Bookmark {
+    pub index: usize,
+    pub line: String,
+    #[serde(default)]
+    pub note: String,
+    /// Is the line not found in the current debug information?
+    #[serde(skip)]
+    pub missing: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, TypeName)]
+pub struct Bookmarks {
+    #[serde(default, rename = "bookmark")]
+    entries: Vec<Bookmark>,
+}
+
+impl Deref for Bookmarks {
+    type Target = Vec<Bookmark>;
+    fn deref(&self) -> &Vec<Bookmark> {
+        &self.entries
+    }
+}
+
+impl DerefMut for Bookmarks {
+    fn deref_mut(&mut self) -> &mut Vec<Bookmark> {
+        &mut self.entries
+    }
+}
+
+impl Bookmarks {
+    /// Returns the file of bookmarks next to the visibility_info file,
+    /// e.g. `vis.bookmarks.toml` for `vis.toml`.
+    pub fn file_path(visibility_info_file: &Path) -> PathBuf {
+        let file_stem = visibility_info_file
+            .file_stem()
+            .map_or("visibility_info".into(), |file_stem| {
+                file_stem.to_string_lossy()
+            });
+        visibility_info_file.with_file_name(format!("{}.bookmarks.toml", file_stem))
+    }
+
+    pub fn try_new(file: &Path) -> Result<Self> {
+        if file.is_file() {
+            Self::read_toml_file(file)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    #[methodlog]
+    pub fn write_toml_file(&self, file: &Path) -> Result<()> {
+        visibility_info::write_file_atomically(
+            file,
+            &toml::to_string(&self).context("failed to change toml into string")?,
+        )
+    }
+
+    pub fn read_toml_file(file: &Path) -> Result<Self> {
+        toml::de::from_str(
+            &fs::read_to_string(file)
+                .with_context(|| format!("failed to read {}", file.display()))?,
+        )
+        .with_context(|| format!("failed to parse {}", file.display()))
+    }
+
+    /// Moves each bookmark to the nearest line which has the same text.
+    ///
+    /// Bookmarks whose lines are not found are kept as missing ones,
+    /// and those moved to the same line are merged.
+    pub fn update_by_debug_info(mut self, debug_info: &str) -> Self {
+        let lines = debug_info.lines().collect::<Vec<_>>();
+        for bookmark in self.entries.iter_mut() {
+            let nearest_index = lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| **line == bookmark.line)
+                .min_by_key(|(index, _)| index.abs_diff(bookmark.index))
+                .map(|(index, _)| index);
+            match nearest_index {
+                Some(index) => {
+                    bookmark.index = index;
+                    bookmark.missing = false;
+                }
+                None => bookmark.missing = true,
+            }
+        }
+        self.entries
+            .sort_by_key(|bookmark| (bookmark.missing, bookmark.index));
+        // Bookmarks moved to the same line are merged into one, keeping their notes.
+        self.entries.dedup_by(|later, earlier| {
+            if later.missing || earlier.missing || later.index != earlier.index {
+                return false;
+            }
+            if earlier.note.is_empty() {
+                earlier.note = std::mem::take(&mut later.note);
+            } else if !later.note.is_empty() && later.note != earlier.note {
+                earlier.note = format!("{} / {}", earlier.note, later.note);
+            }
+            true
+        });
+        self
+    }
+
+    /// Returns the bookmark of the line if exists.
+    pub fn of_line(&self, index: usize) -> Option<&Bookmark> {
+        self.entries
+            .iter()
+            .find(|bookmark| !bookmark.missing && bookmark.index == index)
+    }
+
+    /// Adds a bookmark of the line, or removes it if it has been already bookmarked.
+    pub fn toggle(&mut self, index: usize, line: &str) {
+        match self.position(index) {
+            Some(position) => {
+                self.entries.remove(position);
+            }
+            None => self.insert(index, line, String::new()),
+        }
+    }
+
+    /// Sets the note of the line, adding a bookmark if needed.
+    pub fn annotate(&mut self, index: usize, line: &str, note: String) {
+        match self.position(index) {
+            Some(position) => self.entries[position].note = note,
+            None => self.insert(index, line, note),
+        }
+    }
+
+    fn position(&self, index: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|bookmark| !bookmark.missing && bookmark.index == index)
+    }
+
+    fn insert(&mut self, index: usize, line: &str, note: String) {
+        let position = self
+            .entries
+            .partition_point(|bookmark| !bookmark.missing && bookmark.index < index);
+        self.entries.insert(
+            position,
+            Bookmark {
+                index,
+                line: line.to_string(),
+                note,
+                missing: false,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBUG_INFO: &str = "\
+[DEBUG:func_enter(src/main.rs:10)] main
+[DEBUG:func_enter(src/lib.rs:5)] foo
+[DEBUG:func_exit(src/lib.rs:5)] foo
+[DEBUG:func_exit(src/main.rs:10)] main
+";
+
+    fn bookmark(index: usize, line: &str, note: &str) -> Bookmark {
+        Bookmark {
+            index,
+            line: line.to_string(),
+            note: note.to_string(),
+            missing: false,
+        }
+    }
+
+    #[test]
+    fn move_bookmarks_to_nearest_lines() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.push(bookmark(5, "[DEBUG:func_exit(src/lib.rs:5)] foo", ""));
+        bookmarks.push(bookmark(0, "[DEBUG:func_enter(src/lib.rs:9)] bar", "gone"));
+        let bookmarks = bookmarks.update_by_debug_info(DEBUG_INFO);
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!((bookmarks[0].index, bookmarks[0].missing), (2, false));
+        assert!(bookmarks[1].missing);
+        assert_eq!(bookmarks.of_line(2), Some(&bookmarks[0]));
+        assert_eq!(bookmarks.of_line(0), None);
+    }
+
+    #[test]
+    fn merge_bookmarks_moved_to_same_line() {
+        let line = "[DEBUG:func_enter(src/lib.rs:5)] foo";
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.push(bookmark(0, line, ""));
+        bookmarks.push(bookmark(2, line, "note"));
+        let bookmarks = bookmarks.update_by_debug_info(DEBUG_INFO);
+        assert_eq!(*bookmarks, [bookmark(1, line, "note")]);
+
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.push(bookmark(0, line, "a"));
+        bookmarks.push(bookmark(2, line, "b"));
+        let bookmarks = bookmarks.update_by_debug_info(DEBUG_INFO);
+        assert_eq!(*bookmarks, [bookmark(1, line, "a / b")]);
+    }
+
+    #[test]
+    fn toggle_and_annotate_bookmarks() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.toggle(3, "c");
+        bookmarks.toggle(1, "a");
+        assert_eq!(*bookmarks, [bookmark(1, "a", ""), bookmark(3, "c", "")]);
+        bookmarks.annotate(2, "b", "new".to_string());
+        bookmarks.annotate(1, "a", "edited".to_string());
+        assert_eq!(
+            *bookmarks,
+            [
+                bookmark(1, "a", "edited"),
+                bookmark(2, "b", "new"),
+                bookmark(3, "c", "")
+            ]
+        );
+        bookmarks.toggle(2, "b");
+        assert_eq!(bookmarks.of_line(2), None);
+        assert_eq!(bookmarks.len(), 2);
+    }
+}
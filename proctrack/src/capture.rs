@@ -0,0 +1,182 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use anyhow::{Context, Result};
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// File into which debug information is captured.
+///
+/// A file created in the temporary directory is removed when this is dropped unless it is kept,
+/// in which case its path is printed instead.
+#[derive(Debug)]
+pub struct CaptureFile {
+    path: PathBuf,
+    /// Is the file in the temporary directory?
+    temporary: bool,
+    keep: bool,
+}
+
+impl CaptureFile {
+    /// Uses the file if it is given, or a file in the temporary directory otherwise.
+    pub fn new(path: Option<PathBuf>, keep: bool) -> Self {
+        match path {
+            Some(path) => Self {
+                path,
+                temporary: false,
+                keep: true,
+            },
+            None => Self {
+                path: env::temp_dir().join(format!("proctrack-{}.txt", process::id())),
+                temporary: true,
+                keep,
+            },
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CaptureFile {
+    fn drop(&mut self) {
+        if !self.temporary || !self.path.is_file() {
+            return;
+        }
+        if self.keep {
+            eprintln!("Debug information is kept in {}", self.path.display());
+        } else {
+            // The file is left if it cannot be removed, which does not affect the result.
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Output of the program which is not debug information.
+///
+/// If stdout is a terminal, the output is held until the viewer is closed
+/// because it would break the viewer.
+#[derive(Clone, Debug)]
+pub struct OtherOutput(Option<Arc<Mutex<Vec<u8>>>>);
+
+impl OtherOutput {
+    pub fn new() -> Self {
+        Self(io::stdout().is_terminal().then(Default::default))
+    }
+
+    /// Returns whether the output goes to stdout directly.
+    pub fn is_direct(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn write(&self, bytes: &[u8]) -> Result<()> {
+        match &self.0 {
+            Some(held_output) => held_output.lock().unwrap().extend_from_slice(bytes),
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout
+                    .write_all(bytes)
+                    .and_then(|_| stdout.flush())
+                    .context("failed to write into stdout")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the output held so far.
+    pub fn print_held_output(&self) -> Result<()> {
+        if let Some(held_output) = &self.0 {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(&std::mem::take(&mut *held_output.lock().unwrap()))
+                .and_then(|_| stdout.flush())
+                .context("failed to write into stdout")?;
+        }
+        Ok(())
+    }
+}
+
+/// Starts a thread which writes lines of debug information read from the reader into the file,
+/// and passes the other lines to `other_output`.
+///
+/// The file is created before the thread starts so that it can be opened immediately.
+pub fn spawn_capture<R: Read + Send + 'static>(
+    reader: R,
+    file: &Path,
+    other_output: OtherOutput,
+) -> Result<()> {
+    let mut capture =
+        File::create(file).with_context(|| format!("failed to create {}", file.display()))?;
+    let file = file.to_path_buf();
+    // Errors are ignored since the viewer keeps showing lines captured so far.
+    thread::spawn(move || -> Result<()> {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader
+                .read_until(b'\n', &mut line)
+                .context("failed to read debug information")?
+                == 0
+            {
+                return Ok(());
+            }
+            if line.starts_with(b"[DEBUG:") {
+                capture
+                    .write_all(&line)
+                    .and_then(|_| capture.flush())
+                    .with_context(|| format!("failed to write into {}", file.display()))?;
+            } else {
+                other_output.write(&line)?;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Starts a thread which passes all the output read from the reader to `other_output`.
+fn spawn_pass_through<R: Read + Send + 'static>(mut reader: R, other_output: OtherOutput) {
+    thread::spawn(move || -> Result<()> {
+        let mut buffer = [0; 4096];
+        loop {
+            match reader.read(&mut buffer).context("failed to read output")? {
+                0 => return Ok(()),
+                len => other_output.write(&buffer[..len])?,
+            }
+        }
+    });
+}
+
+/// Spawns the program and captures debug information written into its stderr.
+pub fn spawn_program(
+    command: &[OsString],
+    file: &Path,
+    other_output: OtherOutput,
+) -> Result<Child> {
+    let (program, args) = command.split_first().context("no program is given")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(if other_output.is_direct() {
+            Stdio::inherit()
+        } else {
+            Stdio::piped()
+        })
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {}", program.to_string_lossy()))?;
+    if let Some(stdout) = child.stdout.take() {
+        spawn_pass_through(stdout, other_output.clone());
+    }
+    spawn_capture(child.stderr.take().unwrap(), file, other_output)?;
+    Ok(child)
+}
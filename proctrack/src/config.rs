@@ -0,0 +1,17 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use std::env;
+use std::path::PathBuf;
+
+/// Returns the path of a file in the configuration directory,
+/// i.e. `$XDG_CONFIG_HOME/proctrack` or `~/.config/proctrack`.
+pub fn config_file(file_name: &str) -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("proctrack").join(file_name))
+}
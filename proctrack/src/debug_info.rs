@@ -0,0 +1,608 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::visibility_info::{Visibility, VisibilityMap};
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::time::Duration;
+
+/// Kind of a line of debug information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineKind {
+    FuncEnter,
+    FuncExit,
+    Value,
+    Other,
+}
+
+/// Line which is displayed after visibility information is applied.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DisplayedLine {
+    /// Index of the line in debug information.
+    pub index: usize,
+    /// Depth of nested calls.
+    pub depth: usize,
+    pub kind: LineKind,
+    /// Whether the lines beneath the call are hidden by folding or collapsing.
+    pub folded: bool,
+}
+
+pub fn line_kind(line: &str) -> LineKind {
+    if line.starts_with("[DEBUG:func_enter") {
+        LineKind::FuncEnter
+    } else if line.starts_with("[DEBUG:func_exit") {
+        LineKind::FuncExit
+    } else if line.starts_with("[DEBUG:value") {
+        LineKind::Value
+    } else {
+        LineKind::Other
+    }
+}
+
+/// Returns the function name of a `func_enter` or `func_exit` line.
+pub fn func_name(line: &str) -> Option<&str> {
+    line.split_ascii_whitespace().last()
+}
+
+/// Returns the `file:line` part of a line.
+pub fn location(line: &str) -> Option<&str> {
+    let start = line.find('(')? + 1;
+    let end = start + line[start..].find(')')?;
+    Some(&line[start..end])
+}
+
+/// Returns the value of metadata written after the location,
+/// e.g. `time` and `thread` written by the `timestamp` feature of funclog.
+pub fn metadata<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find('(')?;
+    let start = start + line[start..].find(')')? + 1;
+    let end = start + line[start..].find("] ")?;
+    line[start..end]
+        .split_ascii_whitespace()
+        .find_map(|metadata| {
+            metadata
+                .strip_prefix(key)
+                .and_then(|metadata| metadata.strip_prefix('='))
+        })
+}
+
+/// Returns the time written as `time=<secs>.<nanos>`.
+pub fn time(line: &str) -> Option<Duration> {
+    let (secs, nanos) = metadata(line, "time")?.split_once('.')?;
+    Some(Duration::new(
+        secs.parse().ok()?,
+        format!("{:0<9}", nanos).get(..9)?.parse().ok()?,
+    ))
+}
+
+/// Returns the body of a line, e.g. `x = 1` of a value line.
+pub fn body(line: &str) -> &str {
+    line.find("] ").map_or(line, |idx| &line[idx + 2..])
+}
+
+/// Returns the text displayed for a line,
+/// i.e. the function name of a `func_enter` line or the body of the other lines.
+pub fn content(line: &str, kind: LineKind) -> &str {
+    match kind {
+        LineKind::FuncEnter => func_name(line).unwrap_or_default(),
+        _ => body(line),
+    }
+}
+
+/// Splits the body of a value line into the expression and its value.
+pub fn split_value(body: &str) -> (&str, &str) {
+    body.split_once(" = ").unwrap_or(("", body))
+}
+
+/// Byte offsets of the lines of debug information, which give a line without scanning the text.
+#[derive(Clone, Debug, Default)]
+pub struct LineOffsets(Vec<usize>);
+
+impl LineOffsets {
+    pub fn new(debug_info: &str) -> Self {
+        let mut line_offsets = Self::default();
+        line_offsets.extend(debug_info, 0);
+        line_offsets
+    }
+
+    /// Adds the lines after `start`, which must be the start of a line.
+    pub fn extend(&mut self, debug_info: &str, start: usize) {
+        let len = debug_info.len();
+        self.0.extend(
+            std::iter::once(start)
+                .chain(
+                    debug_info[start..]
+                        .match_indices('\n')
+                        .map(|(idx, _)| start + idx + 1),
+                )
+                .filter(|offset| *offset < len),
+        );
+    }
+
+    /// Returns the line, which is `None` if it is not in debug information.
+    pub fn line<'a>(&self, debug_info: &'a str, index: usize) -> Option<&'a str> {
+        let start = *self.0.get(index)?;
+        let end = self.0.get(index + 1).copied().unwrap_or(debug_info.len());
+        let line = debug_info.get(start..end)?;
+        // Line breaks are removed in the same way as `str::lines`.
+        Some(
+            line.strip_suffix('\n')
+                .map_or(line, |line| line.strip_suffix('\r').unwrap_or(line)),
+        )
+    }
+}
+
+/// Collects lines to be displayed.
+///
+/// A function hidden with its subtree hides itself and all the lines beneath it,
+/// a function hidden as a frame hides only its line and value lines,
+/// and a folded or collapsed call hides only the lines beneath it.
+/// Value lines in shown calls are also filtered by the value filter.
+/// Collapsed calls in `folded_calls` are unfolded.
+pub fn displayed_lines(
+    debug_info: &str,
+    visibility_hash_map: &VisibilityMap,
+    folded_calls: &HashSet<usize>,
+) -> Vec<DisplayedLine> {
+    DisplayedLines::new(debug_info, visibility_hash_map, folded_calls).lines
+}
+
+/// Lines to be displayed, which can be extended by lines appended to debug information.
+#[derive(Clone, Debug, Default)]
+pub struct DisplayedLines {
+    lines: Vec<DisplayedLine>,
+    /// Number of lines of debug information which have been read.
+    num_lines: usize,
+    depth: usize,
+    hidden_depth: Option<usize>,
+    /// Whether the line of each call which is being entered is displayed.
+    frames: Vec<bool>,
+    displayed_depth: usize,
+}
+
+impl Deref for DisplayedLines {
+    type Target = Vec<DisplayedLine>;
+    fn deref(&self) -> &Vec<DisplayedLine> {
+        &self.lines
+    }
+}
+
+impl DisplayedLines {
+    /// Collects lines to be displayed in the same way as [`displayed_lines`].
+    pub fn new(
+        debug_info: &str,
+        visibility_hash_map: &VisibilityMap,
+        folded_calls: &HashSet<usize>,
+    ) -> Self {
+        let mut displayed_lines = Self::default();
+        displayed_lines.extend(debug_info, visibility_hash_map, folded_calls);
+        displayed_lines
+    }
+
+    /// Collects lines to be displayed among the lines appended after the lines read so far.
+    pub fn extend(
+        &mut self,
+        appended_debug_info: &str,
+        visibility_hash_map: &VisibilityMap,
+        folded_calls: &HashSet<usize>,
+    ) {
+        for line in appended_debug_info.lines() {
+            let index = self.num_lines;
+            self.num_lines += 1;
+            let kind = line_kind(line);
+            match kind {
+                LineKind::FuncEnter => {
+                    if self.hidden_depth.is_none() {
+                        let visibility = visibility_hash_map
+                            .get_by_line(line)
+                            .unwrap_or(Visibility::HideSubtree);
+                        self.frames.push(visibility.shows_frame());
+                        match visibility {
+                            Visibility::HideSubtree => self.hidden_depth = Some(self.depth),
+                            Visibility::HideFrame => (),
+                            Visibility::Show | Visibility::Collapse => {
+                                let folded = folded_calls.contains(&index)
+                                    != (visibility == Visibility::Collapse);
+                                self.lines.push(DisplayedLine {
+                                    index,
+                                    depth: self.displayed_depth,
+                                    kind,
+                                    folded,
+                                });
+                                self.displayed_depth += 1;
+                                if folded {
+                                    self.hidden_depth = Some(self.depth);
+                                }
+                            }
+                        }
+                    }
+                    self.depth += 1;
+                }
+                LineKind::FuncExit => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.hidden_depth == Some(self.depth) {
+                        self.hidden_depth = None;
+                    }
+                    if self.hidden_depth.is_none() && self.frames.pop() == Some(true) {
+                        self.displayed_depth -= 1;
+                    }
+                }
+                LineKind::Value => {
+                    if self.hidden_depth.is_none()
+                        && self.frames.last().copied().unwrap_or(true)
+                        && visibility_hash_map.shows_value(line)
+                    {
+                        self.lines.push(DisplayedLine {
+                            index,
+                            depth: self.displayed_depth,
+                            kind,
+                            folded: false,
+                        });
+                    }
+                }
+                LineKind::Other => (),
+            }
+        }
+    }
+}
+
+/// Call of a function.
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub func_name: String,
+    pub location: String,
+    /// Index of the `func_enter` line.
+    pub enter_index: usize,
+    /// Index of the `func_exit` line, which is `None` if the call has not returned.
+    pub exit_index: Option<usize>,
+    pub depth: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Indices of value lines written directly in the call.
+    pub values: Vec<usize>,
+    pub enter_time: Option<Duration>,
+    pub exit_time: Option<Duration>,
+    pub thread: Option<String>,
+}
+
+/// Tree of calls built from debug information.
+#[derive(Clone, Debug, Default)]
+pub struct CallTree {
+    pub calls: Vec<Call>,
+    /// Indices of calls which have no parent.
+    pub roots: Vec<usize>,
+    /// Index of the call which each line belongs to.
+    pub line_calls: Vec<Option<usize>>,
+    /// Indices of the calls of each function in order.
+    pub func_calls: HashMap<String, Vec<usize>>,
+    /// Locations of the calls of each function in the order in which they appear.
+    pub func_locations: HashMap<String, Vec<String>>,
+    /// Calls which are being entered in each thread.
+    stacks: HashMap<Option<String>, Vec<usize>>,
+    /// Thread of the last `func_enter` or `func_exit` line.
+    current_thread: Option<String>,
+}
+
+impl Call {
+    pub fn duration(&self) -> Option<Duration> {
+        self.exit_time?.checked_sub(self.enter_time?)
+    }
+}
+
+impl CallTree {
+    /// Builds a tree of calls.
+    ///
+    /// Calls in different threads are nested separately when threads are written.
+    pub fn new(debug_info: &str) -> Self {
+        let mut call_tree = Self::default();
+        call_tree.extend(debug_info);
+        call_tree
+    }
+
+    /// Adds the calls in the lines appended after the lines read so far.
+    pub fn extend(&mut self, appended_debug_info: &str) {
+        for line in appended_debug_info.lines() {
+            let index = self.line_calls.len();
+            match line_kind(line) {
+                LineKind::FuncEnter => {
+                    self.current_thread = metadata(line, "thread").map(str::to_string);
+                    let stack = self.stacks.entry(self.current_thread.clone()).or_default();
+                    let idx_call = self.calls.len();
+                    let parent = stack.last().copied();
+                    let depth = stack.len();
+                    stack.push(idx_call);
+                    match parent {
+                        Some(parent) => self.calls[parent].children.push(idx_call),
+                        None => self.roots.push(idx_call),
+                    }
+                    self.line_calls.push(Some(idx_call));
+                    self.func_calls
+                        .entry(func_name(line).unwrap_or_default().to_string())
+                        .or_default()
+                        .push(idx_call);
+                    let call_location = location(line).unwrap_or_default();
+                    let locations = self
+                        .func_locations
+                        .entry(func_name(line).unwrap_or_default().to_string())
+                        .or_default();
+                    if !locations.iter().any(|other| other == call_location) {
+                        locations.push(call_location.to_string());
+                    }
+                    self.calls.push(Call {
+                        func_name: func_name(line).unwrap_or_default().to_string(),
+                        location: call_location.to_string(),
+                        enter_index: index,
+                        exit_index: None,
+                        depth,
+                        parent,
+                        children: Vec::new(),
+                        values: Vec::new(),
+                        enter_time: time(line),
+                        exit_time: None,
+                        thread: self.current_thread.clone(),
+                    });
+                }
+                LineKind::FuncExit => {
+                    self.current_thread = metadata(line, "thread").map(str::to_string);
+                    let idx_call = self
+                        .stacks
+                        .entry(self.current_thread.clone())
+                        .or_default()
+                        .pop();
+                    if let Some(idx_call) = idx_call {
+                        self.calls[idx_call].exit_index = Some(index);
+                        self.calls[idx_call].exit_time = time(line);
+                    }
+                    self.line_calls.push(idx_call);
+                }
+                LineKind::Value => {
+                    let idx_call = self
+                        .stacks
+                        .get(&self.current_thread)
+                        .and_then(|stack| stack.last())
+                        .copied();
+                    if let Some(idx_call) = idx_call {
+                        self.calls[idx_call].values.push(index);
+                    }
+                    self.line_calls.push(idx_call);
+                }
+                LineKind::Other => self.line_calls.push(None),
+            }
+        }
+    }
+
+    /// Returns the calls which the line is inside, from the outermost one.
+    pub fn stack(&self, index: usize) -> Vec<usize> {
+        let mut stack = Vec::new();
+        let mut idx_call = self.line_calls.get(index).copied().flatten();
+        while let Some(idx) = idx_call {
+            stack.push(idx);
+            idx_call = self.calls[idx].parent;
+        }
+        stack.reverse();
+        stack
+    }
+
+    /// Returns whether times are written in debug information.
+    pub fn has_time(&self) -> bool {
+        self.calls.iter().any(|call| call.enter_time.is_some())
+    }
+
+    /// Returns the time spent in the call excluding that in its children.
+    pub fn self_time(&self, idx_call: usize) -> Option<Duration> {
+        let call = &self.calls[idx_call];
+        let children_time = call
+            .children
+            .iter()
+            .filter_map(|child| self.calls[*child].duration())
+            .sum();
+        Some(call.duration()?.saturating_sub(children_time))
+    }
+}
+
+/// Reflows an output of `{:?}` like that of `{:#?}`.
+pub fn pretty_debug(value: &str) -> String {
+    const INDENT: &str = "    ";
+    let mut pretty = String::with_capacity(value.len() * 2);
+    let mut indent = 0;
+    let mut chars = value.chars().peekable();
+    macro_rules! new_line {
+        () => {
+            pretty.push('\n');
+            for _ in 0..indent {
+                pretty.push_str(INDENT);
+            }
+            while chars.next_if_eq(&' ').is_some() {}
+        };
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' if c == '"' || is_char_literal(chars.clone()) => {
+                pretty.push(c);
+                let mut escaped = false;
+                for d in chars.by_ref() {
+                    pretty.push(d);
+                    if escaped {
+                        escaped = false;
+                    } else if d == '\\' {
+                        escaped = true;
+                    } else if d == c {
+                        break;
+                    }
+                }
+            }
+            '{' | '[' | '(' => {
+                pretty.push(c);
+                while chars.next_if_eq(&' ').is_some() {}
+                if let Some(&d) = chars.peek() {
+                    if matches!((c, d), ('{', '}') | ('[', ']') | ('(', ')')) {
+                        pretty.push(d);
+                        chars.next();
+                        continue;
+                    }
+                }
+                indent += 1;
+                new_line!();
+            }
+            '}' | ']' | ')' if indent > 0 => {
+                pretty.truncate(pretty.trim_end().len());
+                if !pretty.ends_with(',') {
+                    pretty.push(',');
+                }
+                indent -= 1;
+                new_line!();
+                pretty.push(c);
+            }
+            ',' if indent > 0 => {
+                pretty.push(c);
+                new_line!();
+            }
+            _ => pretty.push(c),
+        }
+    }
+    pretty
+}
+
+/// Returns whether the characters following `'` make a char literal, e.g. `x'` or `\n'`,
+/// rather than a lifetime such as `'static`.
+fn is_char_literal(mut chars: impl Iterator<Item = char>) -> bool {
+    matches!(
+        (chars.next(), chars.next()),
+        (Some('\\'), Some(_)) | (Some(_), Some('\''))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visibility_info::VisibilityInfo;
+
+    #[test]
+    fn pretty_debug_reflows_nested_values() {
+        assert_eq!(
+            pretty_debug("Foo { a: [1, 2], b: Bar(3), c: {} }"),
+            "Foo {\n    a: [\n        1,\n        2,\n    ],\n    b: Bar(\n        3,\n    ),\n    c: {},\n}"
+        );
+        assert_eq!(pretty_debug("3"), "3");
+    }
+
+    #[test]
+    fn pretty_debug_keeps_strings_and_chars() {
+        assert_eq!(
+            pretty_debug(r#"A { s: "{a, \"b\"}", c: '{', d: '\'', e: ',' }"#),
+            "A {\n    s: \"{a, \\\"b\\\"}\",\n    c: '{',\n    d: '\\'',\n    e: ',',\n}"
+        );
+    }
+
+    #[test]
+    fn pretty_debug_reflows_values_after_lifetimes() {
+        assert_eq!(
+            pretty_debug("A { p: PhantomData<&'static str>, m: Marker<'a>, v: [1] }"),
+            "A {\n    p: PhantomData<&'static str>,\n    m: Marker<'a>,\n    v: [\n        1,\n    ],\n}"
+        );
+    }
+
+    const THREADS: &str = "\
+[DEBUG:func_exit(src/lib.rs:1) thread=ThreadId(1)] lost
+[DEBUG:func_enter(src/main.rs:1) thread=ThreadId(1)] main
+[DEBUG:func_enter(src/lib.rs:5) thread=ThreadId(2)] worker
+[DEBUG:value(src/lib.rs:6) thread=ThreadId(2)] x = 1
+[DEBUG:func_enter(src/lib.rs:9) thread=ThreadId(1)] foo
+[DEBUG:value(src/lib.rs:10) thread=ThreadId(1)] y = 2
+[DEBUG:func_enter(src/lib.rs:12) thread=ThreadId(2)] bar
+[DEBUG:func_exit(src/lib.rs:12) thread=ThreadId(2)] bar
+[DEBUG:func_exit(src/lib.rs:9) thread=ThreadId(1)] foo
+[DEBUG:func_exit(src/lib.rs:5) thread=ThreadId(2)] worker
+[DEBUG:func_exit(src/main.rs:1) thread=ThreadId(1)] main
+[DEBUG:func_exit(src/main.rs:1) thread=ThreadId(1)] main
+";
+
+    /// Returns the parent's name and the depth of each call.
+    fn parents(call_tree: &CallTree) -> Vec<(&str, Option<&str>, usize)> {
+        call_tree
+            .calls
+            .iter()
+            .map(|call| {
+                (
+                    call.func_name.as_str(),
+                    call.parent
+                        .map(|parent| call_tree.calls[parent].func_name.as_str()),
+                    call.depth,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nest_calls_in_each_thread() {
+        let call_tree = CallTree::new(THREADS);
+        assert_eq!(
+            parents(&call_tree),
+            [
+                ("main", None, 0),
+                ("worker", None, 0),
+                ("foo", Some("main"), 1),
+                ("bar", Some("worker"), 1),
+            ]
+        );
+        assert_eq!(call_tree.roots, [0, 1]);
+        assert_eq!(call_tree.calls[1].values, [3]);
+        assert_eq!(call_tree.calls[2].values, [5]);
+        assert_eq!(call_tree.calls[1].exit_index, Some(9));
+        assert_eq!(call_tree.stack(6), [1, 3]);
+    }
+
+    #[test]
+    fn ignore_unmatched_exits() {
+        let call_tree = CallTree::new(THREADS);
+        assert_eq!(call_tree.line_calls[0], None);
+        assert_eq!(call_tree.line_calls[11], None);
+        assert_eq!(call_tree.calls[0].exit_index, Some(10));
+        let displayed_lines =
+            DisplayedLines::new(THREADS, &VisibilityMap::default(), &HashSet::new());
+        assert!(displayed_lines.is_empty());
+    }
+
+    #[test]
+    fn extend_by_appended_lines() {
+        let lines = THREADS.split_inclusive('\n').collect::<Vec<_>>();
+        let map = VisibilityMap::from(
+            VisibilityInfo::default()
+                .update_by_debug_info(THREADS)
+                .unwrap(),
+        );
+        let folded_calls = HashSet::from([6]);
+        let call_tree = CallTree::new(THREADS);
+        let displayed_lines = DisplayedLines::new(THREADS, &map, &folded_calls);
+        let line_offsets = LineOffsets::new(THREADS);
+        assert_eq!(displayed_lines.len(), 6);
+        assert!(displayed_lines[5].folded);
+        for i in 0..=lines.len() {
+            let (head, tail) = THREADS.split_at(lines[..i].concat().len());
+            let mut extended_call_tree = CallTree::new(head);
+            extended_call_tree.extend(tail);
+            assert_eq!(parents(&extended_call_tree), parents(&call_tree));
+            assert_eq!(extended_call_tree.line_calls, call_tree.line_calls);
+            assert_eq!(extended_call_tree.roots, call_tree.roots);
+            let mut extended_displayed_lines = DisplayedLines::new(head, &map, &folded_calls);
+            extended_displayed_lines.extend(tail, &map, &folded_calls);
+            assert_eq!(*extended_displayed_lines, *displayed_lines);
+            let mut extended_line_offsets = LineOffsets::new(head);
+            extended_line_offsets.extend(THREADS, head.len());
+            assert_eq!(extended_line_offsets.0, line_offsets.0);
+        }
+    }
+
+    #[test]
+    fn get_lines_by_offsets() {
+        let debug_info = "a\r\nb\n\nc";
+        let line_offsets = LineOffsets::new(debug_info);
+        let lines = (0..5)
+            .map(|index| line_offsets.line(debug_info, index))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [Some("a"), Some("b"), Some(""), Some("c"), None]);
+        assert_eq!(LineOffsets::new("").line("", 0), None);
+    }
+}
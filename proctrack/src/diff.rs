@@ -0,0 +1,509 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::debug_info::{self, CallTree, LineKind};
+use crate::visibility_info::{Visibility, VisibilityMap};
+
+/// Maximum size of the table used to align items of a call.
+///
+/// Items which exceed it are regarded as different except for the common prefix and suffix.
+const MAX_ALIGNMENT_CELLS: usize = 1 << 24;
+
+/// How a row differs between two traces.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiffKind {
+    Same,
+    /// Only in the first trace.
+    Removed,
+    /// Only in the second trace.
+    Added,
+    /// Value lines at the same position whose values differ.
+    Changed,
+}
+
+/// Row of two traces aligned with each other.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DiffRow {
+    /// Index of the line in the first trace.
+    pub left: Option<usize>,
+    /// Index of the line in the second trace.
+    pub right: Option<usize>,
+    /// Depth of nested calls.
+    pub depth: usize,
+    pub kind: LineKind,
+    pub diff: DiffKind,
+}
+
+/// Two traces whose call trees are aligned.
+#[derive(Clone, Debug, Default)]
+pub struct TraceDiff {
+    pub rows: Vec<DiffRow>,
+    /// Row where the sequence of calls diverges first, where value lines are not regarded.
+    pub first_divergence: Option<usize>,
+}
+
+/// Call or value line in a call.
+#[derive(Clone, Copy, Debug)]
+enum Item {
+    Call(usize),
+    Value(usize),
+}
+
+/// Trace which is one side of the diff.
+struct Side<'a> {
+    lines: Vec<&'a str>,
+    call_tree: &'a CallTree,
+}
+
+impl<'a> Side<'a> {
+    fn new(debug_info: &'a str, call_tree: &'a CallTree) -> Self {
+        Self {
+            lines: debug_info.lines().collect(),
+            call_tree,
+        }
+    }
+
+    /// Returns the text which items are matched by,
+    /// i.e. the function name of a call or the expression of a value line.
+    fn key(&self, item: Item) -> &str {
+        match item {
+            Item::Call(idx_call) => &self.call_tree.calls[idx_call].func_name,
+            Item::Value(index) => debug_info::split_value(debug_info::body(self.lines[index])).0,
+        }
+    }
+}
+
+impl TraceDiff {
+    /// Aligns calls of two traces, matching calls by function names and value lines by expressions.
+    ///
+    /// Hidden functions are skipped in the same way as the viewer if visibility information is given,
+    /// and all the functions are shown otherwise.
+    /// The lines beneath collapsed calls are not compared.
+    pub fn new(
+        debug_info_a: &str,
+        call_tree_a: &CallTree,
+        debug_info_b: &str,
+        call_tree_b: &CallTree,
+        visibility_hash_map: Option<&VisibilityMap>,
+    ) -> Self {
+        let a = Side::new(debug_info_a, call_tree_a);
+        let b = Side::new(debug_info_b, call_tree_b);
+        let mut trace_diff = Self::default();
+        let roots = |side: &Side| {
+            let mut calls = Vec::new();
+            push_visible_calls(side, &side.call_tree.roots, visibility_hash_map, &mut calls);
+            calls.into_iter().map(|(_, item)| item).collect::<Vec<_>>()
+        };
+        trace_diff.align_items(&a, &b, &roots(&a), &roots(&b), visibility_hash_map);
+        trace_diff.first_divergence = trace_diff.rows.iter().position(|row| {
+            row.kind == LineKind::FuncEnter
+                && matches!(row.diff, DiffKind::Removed | DiffKind::Added)
+        });
+        trace_diff
+    }
+
+    /// Returns whether the two traces are the same.
+    pub fn is_same(&self) -> bool {
+        self.rows.iter().all(|row| row.diff == DiffKind::Same)
+    }
+
+    /// Returns the first row which differs after the row.
+    pub fn next_difference(&self, i_row: usize) -> Option<usize> {
+        self.rows
+            .iter()
+            .enumerate()
+            .skip(i_row + 1)
+            .find(|(_, row)| row.diff != DiffKind::Same)
+            .map(|(i_row, _)| i_row)
+    }
+
+    /// Returns the last row which differs before the row.
+    pub fn prev_difference(&self, i_row: usize) -> Option<usize> {
+        self.rows[..i_row.min(self.rows.len())]
+            .iter()
+            .rposition(|row| row.diff != DiffKind::Same)
+    }
+
+    /// Returns the first lines of both traces at or after the row.
+    pub fn lines_from(&self, i_row: usize) -> (Option<usize>, Option<usize>) {
+        let rows = self.rows.get(i_row..).unwrap_or_default();
+        (
+            rows.iter().find_map(|row| row.left),
+            rows.iter().find_map(|row| row.right),
+        )
+    }
+
+    /// Pushes rows of the aligned items and the items in them.
+    ///
+    /// Nested calls are handled with a stack of the pairs of each depth instead of recursion
+    /// so that deeply nested calls do not overflow the stack.
+    fn align_items(
+        &mut self,
+        a: &Side,
+        b: &Side,
+        items_a: &[Item],
+        items_b: &[Item],
+        visibility_hash_map: Option<&VisibilityMap>,
+    ) {
+        let is_match = |item_a: &Item, item_b: &Item| {
+            matches!(
+                (item_a, item_b),
+                (Item::Call(_), Item::Call(_)) | (Item::Value(_), Item::Value(_))
+            ) && a.key(*item_a) == b.key(*item_b)
+        };
+        let mut stack = vec![align(items_a, items_b, is_match).into_iter()];
+        while let Some(pairs) = stack.last_mut() {
+            let pair = match pairs.next() {
+                Some(pair) => pair,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            let depth = stack.len() - 1;
+            match pair {
+                (Some(Item::Call(call_a)), Some(Item::Call(call_b))) => {
+                    self.rows.push(DiffRow {
+                        left: Some(a.call_tree.calls[call_a].enter_index),
+                        right: Some(b.call_tree.calls[call_b].enter_index),
+                        depth,
+                        kind: LineKind::FuncEnter,
+                        diff: DiffKind::Same,
+                    });
+                    stack.push(
+                        align(
+                            &items(a, call_a, visibility_hash_map),
+                            &items(b, call_b, visibility_hash_map),
+                            is_match,
+                        )
+                        .into_iter(),
+                    );
+                }
+                (Some(Item::Value(index_a)), Some(Item::Value(index_b))) => {
+                    self.rows.push(DiffRow {
+                        left: Some(index_a),
+                        right: Some(index_b),
+                        depth,
+                        kind: LineKind::Value,
+                        diff: if debug_info::body(a.lines[index_a])
+                            == debug_info::body(b.lines[index_b])
+                        {
+                            DiffKind::Same
+                        } else {
+                            DiffKind::Changed
+                        },
+                    });
+                }
+                (Some(item), None) => {
+                    self.push_one_side(a, item, depth, DiffKind::Removed);
+                    if let Item::Call(idx_call) = item {
+                        let items = items(a, idx_call, visibility_hash_map);
+                        stack.push(
+                            items
+                                .into_iter()
+                                .map(|item| (Some(item), None))
+                                .collect::<Vec<_>>()
+                                .into_iter(),
+                        );
+                    }
+                }
+                (None, Some(item)) => {
+                    self.push_one_side(b, item, depth, DiffKind::Added);
+                    if let Item::Call(idx_call) = item {
+                        let items = items(b, idx_call, visibility_hash_map);
+                        stack.push(
+                            items
+                                .into_iter()
+                                .map(|item| (None, Some(item)))
+                                .collect::<Vec<_>>()
+                                .into_iter(),
+                        );
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Pushes the row of an item which is only in one of the traces.
+    fn push_one_side(&mut self, side: &Side, item: Item, depth: usize, diff: DiffKind) {
+        let (index, kind) = match item {
+            Item::Call(idx_call) => (
+                side.call_tree.calls[idx_call].enter_index,
+                LineKind::FuncEnter,
+            ),
+            Item::Value(index) => (index, LineKind::Value),
+        };
+        let (left, right) = match diff {
+            DiffKind::Removed => (Some(index), None),
+            _ => (None, Some(index)),
+        };
+        self.rows.push(DiffRow {
+            left,
+            right,
+            depth,
+            kind,
+            diff,
+        });
+    }
+}
+
+/// Returns the visibility of the call, where functions not in visibility information are hidden
+/// in the same way as the viewer.
+fn visibility(
+    side: &Side,
+    idx_call: usize,
+    visibility_hash_map: Option<&VisibilityMap>,
+) -> Visibility {
+    visibility_hash_map.map_or(Visibility::Show, |visibility_hash_map| {
+        let call = &side.call_tree.calls[idx_call];
+        visibility_hash_map
+            .get(&call.func_name, &call.location)
+            .unwrap_or(Visibility::HideSubtree)
+    })
+}
+
+/// Pushes the shown calls among the calls with their line indices,
+/// where the calls inside a frame hidden alone are pushed in place of it.
+fn push_visible_calls(
+    side: &Side,
+    calls: &[usize],
+    visibility_hash_map: Option<&VisibilityMap>,
+    items: &mut Vec<(usize, Item)>,
+) {
+    // Calls inside hidden frames are visited with a stack so that deep ones do not overflow it.
+    let mut stack = calls.iter().rev().copied().collect::<Vec<_>>();
+    while let Some(idx_call) = stack.pop() {
+        match visibility(side, idx_call, visibility_hash_map) {
+            Visibility::HideSubtree => (),
+            Visibility::HideFrame => {
+                stack.extend(side.call_tree.calls[idx_call].children.iter().rev())
+            }
+            Visibility::Show | Visibility::Collapse => items.push((
+                side.call_tree.calls[idx_call].enter_index,
+                Item::Call(idx_call),
+            )),
+        }
+    }
+}
+
+/// Returns shown value lines and shown calls directly in the call in the order of lines.
+///
+/// Nothing is returned for a collapsed call.
+fn items(side: &Side, idx_call: usize, visibility_hash_map: Option<&VisibilityMap>) -> Vec<Item> {
+    if visibility(side, idx_call, visibility_hash_map) == Visibility::Collapse {
+        return Vec::new();
+    }
+    let call = &side.call_tree.calls[idx_call];
+    let mut items = call
+        .values
+        .iter()
+        .filter(|index| {
+            visibility_hash_map.is_none_or(|visibility_hash_map| {
+                visibility_hash_map.shows_value(side.lines[**index])
+            })
+        })
+        .map(|index| (*index, Item::Value(*index)))
+        .collect::<Vec<_>>();
+    push_visible_calls(side, &call.children, visibility_hash_map, &mut items);
+    items.sort_by_key(|(index, _)| *index);
+    items.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Aligns two sequences by the longest common subsequence.
+///
+/// Returns pairs of indices in order, where unmatched items are paired with `None`.
+fn align<T: Copy>(
+    a: &[T],
+    b: &[T],
+    is_match: impl Fn(&T, &T) -> bool,
+) -> Vec<(Option<T>, Option<T>)> {
+    let prefix = a
+        .iter()
+        .zip(b)
+        .take_while(|(item_a, item_b)| is_match(item_a, item_b))
+        .count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(item_a, item_b)| is_match(item_a, item_b))
+        .count();
+    let middle_a = &a[prefix..a.len() - suffix];
+    let middle_b = &b[prefix..b.len() - suffix];
+    let (n, m) = (middle_a.len(), middle_b.len());
+
+    let mut pairs = Vec::with_capacity(a.len() + b.len());
+    pairs.extend(a[..prefix].iter().zip(b).map(|(x, y)| (Some(*x), Some(*y))));
+    if n.saturating_mul(m) <= MAX_ALIGNMENT_CELLS {
+        // lcs[i * (m + 1) + j] is the length of the LCS of middle_a[i..] and middle_b[j..].
+        let mut lcs = vec![0_u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if is_match(&middle_a[i], &middle_b[j]) {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if is_match(&middle_a[i], &middle_b[j])
+                && lcs[i * (m + 1) + j] == lcs[(i + 1) * (m + 1) + j + 1] + 1
+            {
+                pairs.push((Some(middle_a[i]), Some(middle_b[j])));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+                pairs.push((Some(middle_a[i]), None));
+                i += 1;
+            } else {
+                pairs.push((None, Some(middle_b[j])));
+                j += 1;
+            }
+        }
+        pairs.extend(middle_a[i..].iter().map(|x| (Some(*x), None)));
+        pairs.extend(middle_b[j..].iter().map(|y| (None, Some(*y))));
+    } else {
+        pairs.extend(middle_a.iter().map(|x| (Some(*x), None)));
+        pairs.extend(middle_b.iter().map(|y| (None, Some(*y))));
+    }
+    pairs.extend(
+        a[a.len() - suffix..]
+            .iter()
+            .zip(&b[b.len() - suffix..])
+            .map(|(x, y)| (Some(*x), Some(*y))),
+    );
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_A: &str = "\
+[DEBUG:func_enter(src/main.rs:1)] main
+[DEBUG:value(src/main.rs:2)] a = 1
+[DEBUG:func_enter(src/lib.rs:1)] foo
+[DEBUG:func_exit(src/lib.rs:1)] foo
+[DEBUG:func_exit(src/main.rs:1)] main
+";
+
+    const TRACE_B: &str = "\
+[DEBUG:func_enter(src/main.rs:1)] main
+[DEBUG:value(src/main.rs:2)] b = 1
+[DEBUG:func_enter(src/lib.rs:1)] foo
+[DEBUG:func_exit(src/lib.rs:1)] foo
+[DEBUG:func_enter(src/lib.rs:5)] bar
+[DEBUG:func_exit(src/lib.rs:5)] bar
+[DEBUG:func_exit(src/main.rs:1)] main
+";
+
+    fn trace_diff(visibility_hash_map: Option<&VisibilityMap>) -> TraceDiff {
+        TraceDiff::new(
+            TRACE_A,
+            &CallTree::new(TRACE_A),
+            TRACE_B,
+            &CallTree::new(TRACE_B),
+            visibility_hash_map,
+        )
+    }
+
+    #[test]
+    fn align_by_longest_common_subsequence() {
+        assert_eq!(
+            align(&[1, 2, 3, 4], &[1, 3, 5, 4], |a, b| a == b),
+            vec![
+                (Some(1), Some(1)),
+                (Some(2), None),
+                (Some(3), Some(3)),
+                (None, Some(5)),
+                (Some(4), Some(4)),
+            ]
+        );
+        assert_eq!(
+            align(&[1, 2], &[], |a, b| a == b),
+            vec![(Some(1), None), (Some(2), None)]
+        );
+    }
+
+    #[test]
+    fn first_divergence_skips_value_lines() {
+        let trace_diff = trace_diff(None);
+        assert_eq!(
+            trace_diff
+                .rows
+                .iter()
+                .map(|row| (row.left, row.right, row.diff))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some(0), Some(0), DiffKind::Same),
+                (Some(1), None, DiffKind::Removed),
+                (None, Some(1), DiffKind::Added),
+                (Some(2), Some(2), DiffKind::Same),
+                (None, Some(4), DiffKind::Added),
+            ]
+        );
+        assert_eq!(trace_diff.first_divergence, Some(4));
+        assert_eq!(trace_diff.next_difference(0), Some(1));
+        assert_eq!(trace_diff.prev_difference(4), Some(2));
+        assert_eq!(trace_diff.lines_from(3), (Some(2), Some(2)));
+    }
+
+    #[test]
+    fn functions_not_in_visibility_info_are_hidden() {
+        let visibility_hash_map = [
+            ("main".to_string(), Visibility::Show),
+            ("foo".to_string(), Visibility::Show),
+        ]
+        .into_iter()
+        .collect::<VisibilityMap>();
+        let trace_diff = trace_diff(Some(&visibility_hash_map));
+        assert_eq!(trace_diff.first_divergence, None);
+        assert!(!trace_diff.is_same());
+    }
+
+    #[test]
+    fn align_deeply_nested_calls() {
+        let depth = 100_000;
+        let deep = "[DEBUG:func_enter(src/lib.rs:1)] f\n".repeat(depth)
+            + "[DEBUG:func_enter(src/lib.rs:5)] g\n[DEBUG:func_exit(src/lib.rs:5)] g\n"
+            + &"[DEBUG:func_exit(src/lib.rs:1)] f\n".repeat(depth);
+        let call_tree = CallTree::new(&deep);
+        let trace_diff = TraceDiff::new(&deep, &call_tree, &deep, &call_tree, None);
+        assert_eq!(trace_diff.rows.len(), depth + 1);
+        assert_eq!(trace_diff.rows[depth].depth, depth);
+        assert!(trace_diff.is_same());
+        let trace_diff = TraceDiff::new(&deep, &call_tree, "", &CallTree::default(), None);
+        assert_eq!(trace_diff.rows.len(), depth + 1);
+        assert!(trace_diff
+            .rows
+            .iter()
+            .all(|row| row.diff == DiffKind::Removed));
+        let visibility_hash_map = toml::from_str::<crate::visibility_info::VisibilityInfo>(
+            r#"
+            [[entries]]
+            func_name = "f"
+            visibility = "hide_frame"
+
+            [[entries]]
+            func_name = "g"
+            visibility = true
+            "#,
+        )
+        .unwrap()
+        .into();
+        let trace_diff = TraceDiff::new(
+            &deep,
+            &call_tree,
+            &deep,
+            &call_tree,
+            Some(&visibility_hash_map),
+        );
+        assert_eq!(trace_diff.rows.len(), 1);
+        assert_eq!(trace_diff.rows[0].left, Some(depth));
+    }
+}
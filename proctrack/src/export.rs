@@ -0,0 +1,595 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::debug_info::{self, CallTree, LineKind};
+use crate::visibility_info::{Visibility, VisibilityMap};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Formats into which debug information is exported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    /// Trace Event Format read by chrome://tracing and Perfetto.
+    Chrome,
+    /// Folded stacks read by flamegraph tools.
+    Folded,
+    /// Single HTML file which shows the call tree.
+    Html,
+}
+
+/// Weights of folded stacks.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum FoldedWeight {
+    /// Number of calls.
+    #[default]
+    Calls,
+    /// Time spent in calls excluding their children in microseconds.
+    Duration,
+}
+
+/// Returns the calls which are not hidden with their nearest shown callers, from the outermost ones.
+///
+/// Hidden functions are handled in the same way as the viewer,
+/// and the calls beneath collapsed calls are not returned.
+/// All the calls are returned if no visibility information is given.
+pub fn visible_calls(
+    call_tree: &CallTree,
+    visibility_hash_map: Option<&VisibilityMap>,
+) -> Vec<(usize, Option<usize>)> {
+    let visibility = |idx_call: usize| {
+        visibility_hash_map.map_or(Visibility::Show, |visibility_hash_map| {
+            let call = &call_tree.calls[idx_call];
+            visibility_hash_map
+                .get(&call.func_name, &call.location)
+                .unwrap_or(Visibility::HideSubtree)
+        })
+    };
+    let mut visible_calls = Vec::with_capacity(call_tree.calls.len());
+    let mut stack = call_tree
+        .roots
+        .iter()
+        .rev()
+        .map(|idx_call| (*idx_call, None))
+        .collect::<Vec<_>>();
+    while let Some((idx_call, parent)) = stack.pop() {
+        let children = call_tree.calls[idx_call].children.iter().rev();
+        match visibility(idx_call) {
+            Visibility::Show => {
+                visible_calls.push((idx_call, parent));
+                stack.extend(children.map(|child| (*child, Some(idx_call))));
+            }
+            Visibility::HideSubtree => (),
+            Visibility::HideFrame => stack.extend(children.map(|child| (*child, parent))),
+            Visibility::Collapse => visible_calls.push((idx_call, parent)),
+        }
+    }
+    visible_calls
+}
+
+/// Returns the last time written in debug information, at which unfinished calls are regarded as returning.
+fn end_time(call_tree: &CallTree) -> Duration {
+    call_tree
+        .calls
+        .iter()
+        .flat_map(|call| [call.enter_time, call.exit_time])
+        .flatten()
+        .max()
+        .unwrap_or_default()
+}
+
+/// Writes calls in Trace Event Format.
+///
+/// If times are not written in debug information, each line is regarded as taking 1 µs.
+pub fn export_chrome<W: Write + ?Sized>(
+    writer: &mut W,
+    debug_info: &str,
+    call_tree: &CallTree,
+    visibility_hash_map: Option<&VisibilityMap>,
+) -> io::Result<()> {
+    let lines = debug_info.lines().collect::<Vec<_>>();
+    let has_time =
+        !call_tree.calls.is_empty() && call_tree.calls.iter().all(|call| call.enter_time.is_some());
+    let start_time = call_tree
+        .calls
+        .iter()
+        .filter_map(|call| call.enter_time)
+        .min()
+        .unwrap_or_default();
+    let end_time = end_time(call_tree);
+    let micros = |duration: Duration| duration.as_secs_f64() * 1e6;
+
+    let mut thread_ids = BTreeMap::new();
+    let mut events = Vec::new();
+    for (idx_call, _) in visible_calls(call_tree, visibility_hash_map) {
+        let call = &call_tree.calls[idx_call];
+        let (ts, dur) = if has_time {
+            let enter_time = call.enter_time.unwrap_or(start_time);
+            (
+                micros(enter_time.saturating_sub(start_time)),
+                micros(
+                    call.exit_time
+                        .unwrap_or(end_time)
+                        .saturating_sub(enter_time),
+                ),
+            )
+        } else {
+            let exit_index = call.exit_index.unwrap_or(lines.len());
+            (
+                call.enter_index as f64,
+                (exit_index - call.enter_index) as f64,
+            )
+        };
+        let num_thread_ids = thread_ids.len();
+        let tid = *thread_ids
+            .entry(call.thread.as_deref().unwrap_or("main"))
+            .or_insert_with(|| {
+                call.thread
+                    .as_deref()
+                    .and_then(|thread| {
+                        thread
+                            .trim_start_matches("ThreadId(")
+                            .trim_end_matches(')')
+                            .parse::<u64>()
+                            .ok()
+                    })
+                    .unwrap_or(num_thread_ids as u64 + 1)
+            });
+        let mut args = Map::new();
+        args.insert("location".to_string(), json!(call.location));
+        for index in call.values.iter().filter(|index| {
+            visibility_hash_map
+                .is_none_or(|visibility_hash_map| visibility_hash_map.shows_value(lines[**index]))
+        }) {
+            let (expr, value) = debug_info::split_value(debug_info::body(lines[*index]));
+            let mut key = expr.to_string();
+            for i in 2.. {
+                if !args.contains_key(&key) {
+                    break;
+                }
+                key = format!("{} #{}", expr, i);
+            }
+            args.insert(key, json!(value));
+        }
+        events.push(json!({
+            "name": call.func_name,
+            "cat": "function",
+            "ph": "X",
+            "ts": ts,
+            "dur": dur,
+            "pid": 1,
+            "tid": tid,
+            "args": args,
+        }));
+    }
+    for (thread, tid) in thread_ids {
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 1,
+            "tid": tid,
+            "args": {"name": thread},
+        }));
+    }
+    serde_json::to_writer(
+        &mut *writer,
+        &json!({
+            "traceEvents": Value::Array(events),
+            "displayTimeUnit": "ms",
+        }),
+    )?;
+    writeln!(writer)
+}
+
+/// Writes calls as folded stacks such as `main;foo;bar 3`.
+///
+/// Hidden calls are elided and time spent in them is counted for their callers,
+/// as well as time spent beneath collapsed calls.
+pub fn export_folded<W: Write + ?Sized>(
+    writer: &mut W,
+    call_tree: &CallTree,
+    visibility_hash_map: Option<&VisibilityMap>,
+    weight: FoldedWeight,
+) -> io::Result<()> {
+    let end_time = end_time(call_tree);
+    let duration = |idx_call: usize| {
+        let call = &call_tree.calls[idx_call];
+        call.enter_time.map_or(Duration::ZERO, |enter_time| {
+            call.exit_time
+                .unwrap_or(end_time)
+                .saturating_sub(enter_time)
+        })
+    };
+    let visible_calls = visible_calls(call_tree, visibility_hash_map);
+    let mut stacks = vec![String::new(); call_tree.calls.len()];
+    let mut folded_stacks = BTreeMap::<&str, u128>::new();
+    let mut children_durations = vec![Duration::ZERO; call_tree.calls.len()];
+    for (idx_call, parent) in visible_calls.iter().copied() {
+        let call = &call_tree.calls[idx_call];
+        stacks[idx_call] = match parent {
+            Some(parent) => format!("{};{}", stacks[parent], call.func_name),
+            None => call.func_name.clone(),
+        };
+        if let Some(parent) = parent {
+            children_durations[parent] += duration(idx_call);
+        }
+    }
+    for (idx_call, _) in visible_calls {
+        let count = match weight {
+            FoldedWeight::Calls => 1,
+            FoldedWeight::Duration => duration(idx_call)
+                .saturating_sub(children_durations[idx_call])
+                .as_micros(),
+        };
+        *folded_stacks.entry(&stacks[idx_call]).or_default() += count;
+    }
+    for (stack, count) in folded_stacks {
+        if count > 0 {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+    }
+    Ok(())
+}
+
+const HTML_STYLE: &str = r#"
+body { font-family: monospace; margin: 1em; }
+header { position: sticky; top: 0; padding: 0.5em 0; background: white; }
+details { margin-left: 1.5em; border-left: 1px solid #ccc; padding-left: 0.5em; }
+details > summary { margin-left: -1em; cursor: pointer; }
+.func { color: #0550ae; font-weight: bold; }
+.location, .time { color: #6e7781; margin-left: 1em; }
+.value { margin-left: 1em; }
+.expr { color: #8250df; }
+.match { background: #fff8c5; }
+"#;
+
+const HTML_SCRIPT: &str = r#"
+const search = document.getElementById("search");
+const count = document.getElementById("count");
+search.addEventListener("input", () => {
+  const query = search.value.toLowerCase();
+  let num_matches = 0;
+  for (const line of document.querySelectorAll(".line")) {
+    const matched = query !== "" && line.textContent.toLowerCase().includes(query);
+    line.classList.toggle("match", matched);
+    if (matched) {
+      num_matches += 1;
+      for (let node = line.parentElement; node; node = node.parentElement) {
+        if (node.tagName === "DETAILS") {
+          node.open = true;
+        }
+      }
+    }
+  }
+  count.textContent = query === "" ? "" : `${num_matches} matches`;
+  const first = document.querySelector(".match");
+  if (first) {
+    first.scrollIntoView({ block: "center" });
+  }
+});
+"#;
+
+/// Escapes characters which have special meanings in HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns a link to the `file:line` location.
+fn location_link(location: &str) -> String {
+    let href = match location.rsplit_once(':') {
+        Some((file, line)) => format!("{}#L{}", file, line),
+        None => location.to_string(),
+    };
+    format!(
+        r#"<a class="location" href="{}">{}</a>"#,
+        escape_html(&href),
+        escape_html(location)
+    )
+}
+
+/// Writes a single HTML file which shows the call tree in the same way as the viewer.
+///
+/// All the functions are shown if no visibility information is given.
+pub fn export_html<W: Write + ?Sized>(
+    writer: &mut W,
+    debug_info: &str,
+    call_tree: &CallTree,
+    visibility_hash_map: Option<&VisibilityMap>,
+    title: &str,
+) -> io::Result<()> {
+    let lines = debug_info.lines().collect::<Vec<_>>();
+    let all_visible;
+    let visibility_hash_map = match visibility_hash_map {
+        Some(visibility_hash_map) => visibility_hash_map,
+        None => {
+            all_visible = call_tree
+                .calls
+                .iter()
+                .map(|call| (call.func_name.clone(), Visibility::Show))
+                .collect();
+            &all_visible
+        }
+    };
+    // Collapsed calls are unfolded and written as closed elements.
+    let collapsed_calls = call_tree
+        .calls
+        .iter()
+        .filter(|call| {
+            visibility_hash_map.get(&call.func_name, &call.location) == Some(Visibility::Collapse)
+        })
+        .map(|call| call.enter_index)
+        .collect::<HashSet<_>>();
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, r#"<html lang="en">"#)?;
+    writeln!(writer, r#"<head><meta charset="utf-8">"#)?;
+    writeln!(writer, "<title>{}</title>", escape_html(title))?;
+    writeln!(writer, "<style>{}</style>", HTML_STYLE)?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(
+        writer,
+        r#"<header><input id="search" type="search" placeholder="Search"> <span id="count"></span></header>"#
+    )?;
+    let mut depth = 0;
+    for displayed_line in
+        debug_info::displayed_lines(debug_info, visibility_hash_map, &collapsed_calls)
+    {
+        for _ in displayed_line.depth..depth {
+            writeln!(writer, "</details>")?;
+        }
+        depth = displayed_line.depth;
+        let line = lines[displayed_line.index];
+        let location = debug_info::location(line).unwrap_or_default();
+        match displayed_line.kind {
+            LineKind::FuncEnter => {
+                let time = call_tree.line_calls[displayed_line.index]
+                    .and_then(|idx_call| call_tree.calls[idx_call].duration())
+                    .map_or(String::new(), |time| {
+                        format!(r#"<span class="time">{:.1?}</span>"#, time)
+                    });
+                writeln!(
+                    writer,
+                    r#"<details{}><summary class="line"><span class="func">{}</span>{}{}</summary>"#,
+                    if collapsed_calls.contains(&displayed_line.index) {
+                        ""
+                    } else {
+                        " open"
+                    },
+                    escape_html(debug_info::func_name(line).unwrap_or_default()),
+                    location_link(location),
+                    time
+                )?;
+                depth += 1;
+            }
+            _ => {
+                let (expr, value) = debug_info::split_value(debug_info::body(line));
+                let expr = if expr.is_empty() {
+                    String::new()
+                } else {
+                    format!(r#"<span class="expr">{}</span> = "#, escape_html(expr))
+                };
+                writeln!(
+                    writer,
+                    r#"<div class="line value">{}{}{}</div>"#,
+                    expr,
+                    escape_html(value),
+                    location_link(location)
+                )?;
+            }
+        }
+    }
+    for _ in 0..depth {
+        writeln!(writer, "</details>")?;
+    }
+    writeln!(writer, "<script>{}</script>", HTML_SCRIPT)?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visibility_info::VisibilityInfo;
+
+    /// `main` calls `frame` hidden as a frame, which calls `foo` calling hidden `bar`,
+    /// and `main` calls collapsed `col`, which calls `baz`.
+    const DEBUG_INFO: &str = "\
+[DEBUG:func_enter(src/main.rs:1) time=0.000000] main
+[DEBUG:func_enter(src/lib.rs:1) time=0.000001] frame
+[DEBUG:func_enter(src/lib.rs:5) time=0.000002] foo
+[DEBUG:func_enter(src/lib.rs:9) time=0.000003] bar
+[DEBUG:func_exit(src/lib.rs:9) time=0.000004] bar
+[DEBUG:func_exit(src/lib.rs:5) time=0.000005] foo
+[DEBUG:func_exit(src/lib.rs:1) time=0.000006] frame
+[DEBUG:func_enter(src/lib.rs:12) time=0.000006] col
+[DEBUG:func_enter(src/lib.rs:15) time=0.000007] baz
+[DEBUG:func_exit(src/lib.rs:15) time=0.000008] baz
+[DEBUG:func_exit(src/lib.rs:12) time=0.000009] col
+[DEBUG:func_exit(src/main.rs:1) time=0.000010] main
+";
+
+    const VISIBILITY_INFO: &str = r#"
+        [[entries]]
+        func_name = "bar"
+        visibility = false
+
+        [[entries]]
+        func_name = "baz"
+        visibility = true
+
+        [[entries]]
+        func_name = "col"
+        visibility = "collapse"
+
+        [[entries]]
+        func_name = "foo"
+        visibility = true
+
+        [[entries]]
+        func_name = "frame"
+        visibility = "hide_frame"
+
+        [[entries]]
+        func_name = "main"
+        visibility = true
+    "#;
+
+    fn visibility_hash_map() -> VisibilityMap {
+        toml::from_str::<VisibilityInfo>(VISIBILITY_INFO)
+            .unwrap()
+            .into()
+    }
+
+    /// Returns the names of the calls and their parents.
+    fn names<'a>(
+        call_tree: &'a CallTree,
+        calls: &[(usize, Option<usize>)],
+    ) -> Vec<(&'a str, Option<&'a str>)> {
+        calls
+            .iter()
+            .map(|(idx_call, parent)| {
+                (
+                    call_tree.calls[*idx_call].func_name.as_str(),
+                    parent.map(|parent| call_tree.calls[parent].func_name.as_str()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn visible_calls_skip_hidden_frames_and_subtrees() {
+        let call_tree = CallTree::new(DEBUG_INFO);
+        let visibility_hash_map = visibility_hash_map();
+        assert_eq!(
+            names(
+                &call_tree,
+                &visible_calls(&call_tree, Some(&visibility_hash_map))
+            ),
+            [("main", None), ("foo", Some("main")), ("col", Some("main")),]
+        );
+    }
+
+    #[test]
+    fn all_calls_are_visible_without_visibility_info() {
+        let call_tree = CallTree::new(DEBUG_INFO);
+        assert_eq!(
+            names(&call_tree, &visible_calls(&call_tree, None)),
+            [
+                ("main", None),
+                ("frame", Some("main")),
+                ("foo", Some("frame")),
+                ("bar", Some("foo")),
+                ("col", Some("main")),
+                ("baz", Some("col")),
+            ]
+        );
+        let unknown = VisibilityMap::default();
+        assert!(visible_calls(&call_tree, Some(&unknown)).is_empty());
+    }
+
+    fn folded(
+        debug_info: &str,
+        visibility_hash_map: Option<&VisibilityMap>,
+        weight: FoldedWeight,
+    ) -> String {
+        let mut folded = Vec::new();
+        export_folded(
+            &mut folded,
+            &CallTree::new(debug_info),
+            visibility_hash_map,
+            weight,
+        )
+        .unwrap();
+        String::from_utf8(folded).unwrap()
+    }
+
+    #[test]
+    fn count_time_of_hidden_and_collapsed_calls_for_callers() {
+        let visibility_hash_map = visibility_hash_map();
+        assert_eq!(
+            folded(
+                DEBUG_INFO,
+                Some(&visibility_hash_map),
+                FoldedWeight::Duration
+            ),
+            "main 4\nmain;col 3\nmain;foo 3\n"
+        );
+        assert_eq!(
+            folded(DEBUG_INFO, Some(&visibility_hash_map), FoldedWeight::Calls),
+            "main 1\nmain;col 1\nmain;foo 1\n"
+        );
+    }
+
+    #[test]
+    fn subtract_time_of_children() {
+        assert_eq!(
+            folded(DEBUG_INFO, None, FoldedWeight::Duration),
+            "main 2\nmain;col 2\nmain;col;baz 1\nmain;frame 2\nmain;frame;foo 2\nmain;frame;foo;bar 1\n"
+        );
+        // Unfinished calls return at the last time, and stacks without time are omitted.
+        let unfinished = DEBUG_INFO.lines().take(4).collect::<Vec<_>>().join("\n");
+        assert_eq!(
+            folded(&unfinished, None, FoldedWeight::Duration),
+            "main 1\nmain;frame 1\nmain;frame;foo 1\n"
+        );
+    }
+
+    #[test]
+    fn escape_special_characters_of_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain"), "plain");
+        assert_eq!(
+            location_link("src/<a>.rs:12"),
+            r#"<a class="location" href="src/&lt;a&gt;.rs#L12">src/&lt;a&gt;.rs:12</a>"#
+        );
+    }
+
+    #[test]
+    fn write_call_tree_as_html() {
+        let debug_info =
+            DEBUG_INFO.replacen('\n', "\n[DEBUG:value(src/main.rs:2)] v = [\"<b>\"]\n", 1);
+        let call_tree = CallTree::new(&debug_info);
+        let visibility_hash_map = visibility_hash_map();
+        let mut html = Vec::new();
+        export_html(
+            &mut html,
+            &debug_info,
+            &call_tree,
+            Some(&visibility_hash_map),
+            "a & b",
+        )
+        .unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<title>a &amp; b</title>"));
+        assert!(html.contains(r#"<span class="expr">v</span> = [&quot;&lt;b&gt;&quot;]"#));
+        assert!(
+            html.contains(r#"<details open><summary class="line"><span class="func">foo</span>"#)
+        );
+        assert!(html.contains(r#"<details><summary class="line"><span class="func">col</span>"#));
+        assert!(html.contains(r#"<span class="func">baz</span>"#));
+        assert!(!html.contains(r#"<span class="func">bar</span>"#));
+        assert!(!html.contains(r#"<span class="func">frame</span>"#));
+        assert_eq!(
+            html.matches("<details").count(),
+            html.matches("</details>").count()
+        );
+    }
+}
@@ -3,49 +3,564 @@
 // Released under the MIT license.
 // see https://opensource.org/licenses/mit-license.php
 
+mod bookmark;
+mod capture;
+mod config;
+mod debug_info;
+mod diff;
+mod export;
+mod render;
+mod stats;
 mod system;
+mod theme;
 mod visibility_info;
 
+use self::bookmark::Bookmarks;
+use self::capture::{CaptureFile, OtherOutput};
+use self::debug_info::CallTree;
+use self::diff::TraceDiff;
+use self::export::{ExportFormat, FoldedWeight};
+use self::system::handler::keybinding::{KeyBindings, PRESETS};
+use self::theme::Theme;
 use self::visibility_info::VisibilityInfo;
-use anyhow::{Context, Result};
-use clap::{Parser, ValueHint};
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueHint};
 use proctrack::funclog::{funclog, methodlog_static};
 use proctrack::typename_derive::{TypeName, TypeNameStatic};
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 #[funclog]
 fn main() -> Result<()> {
     Cli::run()
 }
 #[derive(Parser, TypeName, TypeNameStatic)]
-#[clap(author, version, about, after_help = concat!("Repository: ", env!("CARGO_PKG_REPOSITORY")))]
+#[clap(
+    author,
+    version,
+    about,
+    after_help = concat!("Repository: ", env!("CARGO_PKG_REPOSITORY")),
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub(crate) struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(
+        required = true,
         value_hint(ValueHint::FilePath),
-        help = "Debug information written by using proctrack crate."
+        help = "Debug information written by using proctrack crate. If this is `-`, it is read from stdin."
     )]
-    debug_info: PathBuf,
+    debug_info: Option<PathBuf>,
     #[clap(
+        required = true,
         value_hint(ValueHint::FilePath),
         help = "TOML file which saves visibility information. If this file does not exsist, a new file is created."
     )]
-    visibility_info: PathBuf,
+    visibility_info: Option<PathBuf>,
+    #[clap(flatten)]
+    viewer: ViewerArgs,
+    #[clap(
+        long,
+        help = "Watches debug_info and reads lines appended to it, e.g. while the program is running."
+    )]
+    follow: bool,
+    #[clap(
+        long,
+        value_hint(ValueHint::FilePath),
+        help = "File into which debug information read from stdin is written. [default: a file in the temporary directory, which is removed when the viewer is closed]"
+    )]
+    capture: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Keeps the file in the temporary directory into which debug information is captured, whose path is printed when the viewer is closed."
+    )]
+    keep_capture: bool,
+    #[clap(
+        long,
+        value_hint(ValueHint::FilePath),
+        help = "Debug information compared with debug_info in the diff mode."
+    )]
+    diff: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[clap(
+        about = "Runs a program and views debug information written into its stderr while it is running."
+    )]
+    Run {
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which saves visibility information. If this file does not exsist, a new file is created."
+        )]
+        visibility_info: PathBuf,
+        #[clap(flatten)]
+        viewer: ViewerArgs,
+        #[clap(
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "File into which debug information is written. [default: a file in the temporary directory, which is removed when the viewer is closed]"
+        )]
+        capture: Option<PathBuf>,
+        #[clap(
+            long,
+            help = "Keeps the file in the temporary directory into which debug information is captured, whose path is printed when the viewer is closed."
+        )]
+        keep_capture: bool,
+        #[clap(
+            last = true,
+            required = true,
+            value_hint(ValueHint::CommandWithArguments),
+            help = "Program and its arguments."
+        )]
+        command: Vec<OsString>,
+    },
+    #[clap(about = "Prints debug information to stdout in the same way as the viewer.")]
+    Render {
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "Debug information written by using proctrack crate."
+        )]
+        debug_info: PathBuf,
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which saves visibility information. All the functions are shown if this file does not exsist."
+        )]
+        visibility_info: PathBuf,
+        #[clap(
+            long,
+            help = "Profile of visibility information which is used. [default: the top-level one]"
+        )]
+        profile: Option<String>,
+        #[clap(long, help = "Colors the output with ANSI escape sequences.")]
+        color: bool,
+        #[clap(long, help = "Prints line numbers.")]
+        line_number: bool,
+        #[clap(
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which defines colors and highlighting rules. [default: ~/.config/proctrack/theme.toml]"
+        )]
+        theme: Option<PathBuf>,
+    },
+    #[clap(about = "Exports debug information into another format.")]
+    Export {
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "Debug information written by using proctrack crate."
+        )]
+        debug_info: PathBuf,
+        #[clap(long, value_enum, help = "Format of the output.")]
+        format: ExportFormat,
+        #[clap(
+            long,
+            value_enum,
+            default_value_t,
+            help = "Weight of folded stacks. `duration` requires debug information written with timestamp feature."
+        )]
+        weight: FoldedWeight,
+        #[clap(
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which saves visibility information. Hidden functions are not exported if this is given."
+        )]
+        visibility_info: Option<PathBuf>,
+        #[clap(
+            long,
+            requires = "visibility_info",
+            help = "Profile of visibility information which is used. [default: the top-level one]"
+        )]
+        profile: Option<String>,
+        #[clap(
+            short,
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "File into which the output is written. [default: stdout]"
+        )]
+        output: Option<PathBuf>,
+    },
+    #[clap(about = "Prints differences between two traces by aligning their calls.")]
+    Diff {
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "Debug information written by using proctrack crate."
+        )]
+        debug_info_a: PathBuf,
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "Debug information compared with the first one."
+        )]
+        debug_info_b: PathBuf,
+        #[clap(
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which saves visibility information. Hidden functions are not compared if this is given."
+        )]
+        visibility_info: Option<PathBuf>,
+        #[clap(
+            long,
+            requires = "visibility_info",
+            help = "Profile of visibility information which is used. [default: the top-level one]"
+        )]
+        profile: Option<String>,
+        #[clap(long, help = "Colors the output with ANSI escape sequences.")]
+        color: bool,
+        #[clap(long, help = "Prints line numbers of both traces.")]
+        line_number: bool,
+        #[clap(
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which defines colors and highlighting rules. [default: ~/.config/proctrack/theme.toml]"
+        )]
+        theme: Option<PathBuf>,
+    },
+    #[clap(
+        about = "Removes stale entries, i.e. functions not found in debug information, from visibility information."
+    )]
+    Prune {
+        #[clap(
+            value_hint(ValueHint::FilePath),
+            help = "TOML file which saves visibility information."
+        )]
+        visibility_info: PathBuf,
+        #[clap(
+            long,
+            help = "Profile of visibility information which is pruned. [default: the top-level one]"
+        )]
+        profile: Option<String>,
+        #[clap(
+            long,
+            value_hint(ValueHint::FilePath),
+            help = "Debug information by which entries are marked as stale. If this is not given, entries marked by the last viewer are pruned."
+        )]
+        debug_info: Option<PathBuf>,
+        #[clap(
+            long,
+            value_name = "DAYS",
+            help = "Prunes only entries which have not been seen for the days."
+        )]
+        older_than: Option<u64>,
+        #[clap(long, help = "Prints entries to be pruned without writing the file.")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args)]
+struct ViewerArgs {
+    #[clap(
+        long,
+        value_hint(ValueHint::FilePath),
+        help = "TOML file which defines keybindings. [default: ~/.config/proctrack/keys.toml]"
+    )]
+    keybindings: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint(ValueHint::FilePath),
+        help = "TOML file which defines colors and highlighting rules. [default: ~/.config/proctrack/theme.toml]"
+    )]
+    theme: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Profile of visibility information which is selected first. If this does not exist, a new profile is created. [default: the top-level one]"
+    )]
+    profile: Option<String>,
 }
 
 impl Cli {
     #[methodlog_static]
     fn run() -> Result<()> {
         let args = Cli::parse();
-        let debug_info = fs::read_to_string(&args.debug_info)
-            .with_context(|| format!("failed to read {}", args.debug_info.display()))?;
-        let visibility_info =
-            VisibilityInfo::try_new(&args.visibility_info)?.update_by_debug_info(&debug_info)?;
+        match args.command {
+            Some(Command::Run {
+                visibility_info,
+                viewer,
+                capture,
+                keep_capture,
+                command,
+            }) => {
+                let capture_file = CaptureFile::new(capture, keep_capture);
+                let other_output = OtherOutput::new();
+                let mut child =
+                    capture::spawn_program(&command, capture_file.path(), other_output.clone())?;
+                let result = Self::view(
+                    capture_file.path().to_path_buf(),
+                    visibility_info,
+                    viewer,
+                    true,
+                    None,
+                );
+                // The program is stopped if it is still running when the viewer is closed.
+                let _ = child.kill();
+                child.wait().context("failed to wait for the program")?;
+                other_output.print_held_output()?;
+                result
+            }
+            Some(Command::Render {
+                debug_info,
+                visibility_info,
+                profile,
+                color,
+                line_number,
+                theme,
+            }) => {
+                let debug_info = fs::read_to_string(&debug_info)
+                    .with_context(|| format!("failed to read {}", debug_info.display()))?;
+                let visibility_info = VisibilityInfo::read_if_exists(&visibility_info)?
+                    .with_profile(profile.as_deref())?
+                    .update_by_debug_info(&debug_info)?;
+                let theme = load_theme(theme.as_deref())?;
+                write_output(None, |writer| {
+                    render::render(
+                        writer,
+                        &debug_info,
+                        &visibility_info.into(),
+                        &theme,
+                        color,
+                        line_number,
+                    )
+                })
+            }
+            Some(Command::Export {
+                debug_info: debug_info_file,
+                format,
+                weight,
+                visibility_info,
+                profile,
+                output,
+            }) => {
+                let debug_info = fs::read_to_string(&debug_info_file)
+                    .with_context(|| format!("failed to read {}", debug_info_file.display()))?;
+                let visibility_hash_map = match visibility_info {
+                    Some(visibility_info) => Some(
+                        VisibilityInfo::read_if_exists(&visibility_info)?
+                            .with_profile(profile.as_deref())?
+                            .update_by_debug_info(&debug_info)?
+                            .into(),
+                    ),
+                    None => None,
+                };
+                let call_tree = CallTree::new(&debug_info);
+                if format == ExportFormat::Folded
+                    && weight == FoldedWeight::Duration
+                    && !call_tree.has_time()
+                {
+                    bail!("times are not written in {}", debug_info_file.display());
+                }
+                write_output(output.as_deref(), |writer| match format {
+                    ExportFormat::Chrome => export::export_chrome(
+                        writer,
+                        &debug_info,
+                        &call_tree,
+                        visibility_hash_map.as_ref(),
+                    ),
+                    ExportFormat::Folded => export::export_folded(
+                        writer,
+                        &call_tree,
+                        visibility_hash_map.as_ref(),
+                        weight,
+                    ),
+                    ExportFormat::Html => export::export_html(
+                        writer,
+                        &debug_info,
+                        &call_tree,
+                        visibility_hash_map.as_ref(),
+                        &debug_info_file.display().to_string(),
+                    ),
+                })
+            }
+            Some(Command::Diff {
+                debug_info_a: debug_info_file_a,
+                debug_info_b: debug_info_file_b,
+                visibility_info,
+                profile,
+                color,
+                line_number,
+                theme,
+            }) => {
+                let debug_info_a = fs::read_to_string(&debug_info_file_a)
+                    .with_context(|| format!("failed to read {}", debug_info_file_a.display()))?;
+                let debug_info_b = fs::read_to_string(&debug_info_file_b)
+                    .with_context(|| format!("failed to read {}", debug_info_file_b.display()))?;
+                let visibility_hash_map = match visibility_info {
+                    Some(visibility_info) => Some(
+                        VisibilityInfo::read_if_exists(&visibility_info)?
+                            .with_profile(profile.as_deref())?
+                            .update_by_debug_info(
+                                &[debug_info_a.as_str(), &debug_info_b].join("\n"),
+                            )?
+                            .into(),
+                    ),
+                    None => None,
+                };
+                let trace_diff = TraceDiff::new(
+                    &debug_info_a,
+                    &CallTree::new(&debug_info_a),
+                    &debug_info_b,
+                    &CallTree::new(&debug_info_b),
+                    visibility_hash_map.as_ref(),
+                );
+                let theme = load_theme(theme.as_deref())?;
+                write_output(None, |writer| {
+                    render::render_diff(
+                        writer,
+                        &trace_diff,
+                        (&debug_info_file_a.display().to_string(), &debug_info_a),
+                        (&debug_info_file_b.display().to_string(), &debug_info_b),
+                        &theme,
+                        color,
+                        line_number,
+                    )
+                })
+            }
+            Some(Command::Prune {
+                visibility_info: visibility_info_file,
+                profile,
+                debug_info,
+                older_than,
+                dry_run,
+            }) => {
+                let mut visibility_info = VisibilityInfo::read_toml_file(&visibility_info_file)?
+                    .with_profile(profile.as_deref())?;
+                if let Some(debug_info_file) = debug_info {
+                    let debug_info = fs::read_to_string(&debug_info_file)
+                        .with_context(|| format!("failed to read {}", debug_info_file.display()))?;
+                    visibility_info = visibility_info.mark_by_debug_info(&debug_info)?;
+                }
+                let pruned =
+                    visibility_info
+                        .prune(older_than.map(|days| {
+                            visibility_info::now().saturating_sub(days * 24 * 60 * 60)
+                        }));
+                if !dry_run {
+                    visibility_info.write_toml_file(&visibility_info_file)?;
+                }
+                write_output(None, |writer| {
+                    for entry in pruned {
+                        match entry.location {
+                            Some(location) => {
+                                writeln!(writer, "{} ({})", entry.func_name, location)?
+                            }
+                            None => writeln!(writer, "{}", entry.func_name)?,
+                        }
+                    }
+                    Ok(())
+                })
+            }
+            None => {
+                let debug_info = args.debug_info.unwrap();
+                let visibility_info = args.visibility_info.unwrap();
+                if debug_info == Path::new("-") {
+                    let capture_file = CaptureFile::new(args.capture, args.keep_capture);
+                    let other_output = OtherOutput::new();
+                    capture::spawn_capture(io::stdin(), capture_file.path(), other_output.clone())?;
+                    let result = Self::view(
+                        capture_file.path().to_path_buf(),
+                        visibility_info,
+                        args.viewer,
+                        true,
+                        args.diff,
+                    );
+                    other_output.print_held_output()?;
+                    result
+                } else {
+                    Self::view(
+                        debug_info,
+                        visibility_info,
+                        args.viewer,
+                        args.follow,
+                        args.diff,
+                    )
+                }
+            }
+        }
+    }
+
+    #[methodlog_static]
+    fn view(
+        debug_info_file: PathBuf,
+        visibility_info_file: PathBuf,
+        viewer: ViewerArgs,
+        follow: bool,
+        diff_file: Option<PathBuf>,
+    ) -> Result<()> {
+        let debug_info = fs::read_to_string(&debug_info_file)
+            .with_context(|| format!("failed to read {}", debug_info_file.display()))?;
+        // The time is got first so that changes made while reading are detected on saving.
+        let visibility_info_modified = visibility_info::modified_time(&visibility_info_file);
+        let mut visibility_info = VisibilityInfo::try_new(&visibility_info_file)?;
+        let saved_visibility_info = visibility_info.clone();
+        visibility_info.select_profile(viewer.profile.as_deref());
+        let visibility_info = visibility_info.update_by_debug_info(&debug_info)?;
+        let bookmarks = Bookmarks::try_new(&Bookmarks::file_path(&visibility_info_file))?
+            .update_by_debug_info(&debug_info);
+        let key_bindings = viewer.key_bindings()?;
+        let theme = viewer.theme()?;
         system::activate(
-            args.debug_info,
-            args.visibility_info,
+            debug_info_file,
+            visibility_info_file,
             debug_info,
             visibility_info,
+            saved_visibility_info,
+            visibility_info_modified,
+            bookmarks,
+            key_bindings,
+            theme,
+            follow,
+            diff_file,
         )
     }
 }
+
+impl ViewerArgs {
+    fn key_bindings(&self) -> Result<KeyBindings> {
+        match (&self.keybindings, config::config_file("keys.toml")) {
+            (Some(file), _) => KeyBindings::read_toml_file(file),
+            (None, Some(file)) => KeyBindings::try_new(&file),
+            (None, None) => Ok(KeyBindings::preset(PRESETS[0]).unwrap()),
+        }
+    }
+
+    fn theme(&self) -> Result<Theme> {
+        load_theme(self.theme.as_deref())
+    }
+}
+
+/// Reads the theme from the file, or from the configuration directory if no file is given.
+fn load_theme(file: Option<&Path>) -> Result<Theme> {
+    match (file, config::config_file("theme.toml")) {
+        (Some(file), _) => Theme::read_toml_file(file),
+        (None, Some(file)) => Theme::try_new(&file),
+        (None, None) => Ok(Theme::default()),
+    }
+}
+
+/// Writes the output into the file, or into stdout if no file is given.
+fn write_output(
+    file: Option<&Path>,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<()> {
+    match file {
+        Some(file) => {
+            let mut writer = io::BufWriter::new(
+                fs::File::create(file)
+                    .with_context(|| format!("failed to create {}", file.display()))?,
+            );
+            write(&mut writer)
+                .and_then(|_| writer.flush())
+                .with_context(|| format!("failed to write into {}", file.display()))
+        }
+        None => {
+            let mut stdout = io::BufWriter::new(io::stdout().lock());
+            match write(&mut stdout).and_then(|_| stdout.flush()) {
+                // The output may be piped into a command such as `head`.
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                    Err(err).context("failed to write into stdout")
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::debug_info::{self, LineKind};
+use crate::diff::{DiffKind, TraceDiff};
+use crate::theme::Theme;
+use crate::visibility_info::VisibilityMap;
+use crossterm::style::{Attribute, ContentStyle};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use tui::style::{Color, Modifier, Style};
+
+/// Writes debug information as plain text in the same way as the DebugInfo pane.
+pub fn render<W: Write + ?Sized>(
+    writer: &mut W,
+    debug_info: &str,
+    visibility_hash_map: &VisibilityMap,
+    theme: &Theme,
+    color: bool,
+    line_number: bool,
+) -> io::Result<()> {
+    let lines = debug_info.lines().collect::<Vec<_>>();
+    let depth_style = content_style(Style::default().fg(theme.depth), color);
+    for (i_displayed, displayed_line) in
+        debug_info::displayed_lines(debug_info, visibility_hash_map, &HashSet::new())
+            .into_iter()
+            .enumerate()
+    {
+        let content = debug_info::content(lines[displayed_line.index], displayed_line.kind);
+        if line_number {
+            write!(
+                writer,
+                "{}",
+                depth_style.apply(format!("{:>3} ", i_displayed + 1))
+            )?;
+        }
+        if displayed_line.depth > 0 {
+            write!(
+                writer,
+                "{}",
+                depth_style.apply("| ".repeat(displayed_line.depth))
+            )?;
+        }
+        write!(
+            writer,
+            "{}",
+            content_style(theme.content_style(content, displayed_line.kind), color).apply(content)
+        )?;
+        if displayed_line.folded {
+            write!(writer, "{}", depth_style.apply(" {...}"))?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Writes two traces aligned with each other in a similar way to `diff -u`.
+///
+/// Rows are prefixed with `-` if they are only in the first trace, `+` if only in the second one,
+/// and `!` if their values differ, which are written as `expr = old -> new`.
+pub fn render_diff<W: Write + ?Sized>(
+    writer: &mut W,
+    trace_diff: &TraceDiff,
+    (name_a, debug_info_a): (&str, &str),
+    (name_b, debug_info_b): (&str, &str),
+    theme: &Theme,
+    color: bool,
+    line_number: bool,
+) -> io::Result<()> {
+    let lines_a = debug_info_a.lines().collect::<Vec<_>>();
+    let lines_b = debug_info_b.lines().collect::<Vec<_>>();
+    let depth_style = content_style(Style::default().fg(theme.depth), color);
+    let line_number_string =
+        |index: Option<usize>| index.map_or("end".to_string(), |index| (index + 1).to_string());
+    match trace_diff.first_divergence {
+        Some(i_row) => {
+            let (left, right) = trace_diff.lines_from(i_row);
+            writeln!(
+                writer,
+                "First divergence: {}:{} / {}:{}",
+                name_a,
+                line_number_string(left),
+                name_b,
+                line_number_string(right)
+            )?;
+        }
+        None if trace_diff.is_same() => writeln!(writer, "No difference")?,
+        None => writeln!(writer, "No divergence in calls")?,
+    }
+    for row in trace_diff.rows.iter() {
+        let (mark, mark_color) = match row.diff {
+            DiffKind::Same => (' ', theme.depth),
+            DiffKind::Removed => ('-', theme.removed),
+            DiffKind::Added => ('+', theme.added),
+            DiffKind::Changed => ('!', theme.changed),
+        };
+        write!(
+            writer,
+            "{} ",
+            content_style(Style::default().fg(mark_color), color).apply(mark)
+        )?;
+        if line_number {
+            let column =
+                |index: Option<usize>| index.map_or(String::new(), |index| (index + 1).to_string());
+            write!(
+                writer,
+                "{}",
+                depth_style.apply(format!("{:>4} {:>4} ", column(row.left), column(row.right)))
+            )?;
+        }
+        if row.depth > 0 {
+            write!(writer, "{}", depth_style.apply("| ".repeat(row.depth)))?;
+        }
+        let line = match (row.left, row.right) {
+            (Some(left), _) => lines_a[left],
+            (None, Some(right)) => lines_b[right],
+            (None, None) => unreachable!(),
+        };
+        let content = debug_info::content(line, row.kind);
+        let style = match row.diff {
+            DiffKind::Same => theme.content_style(content, row.kind),
+            _ if row.kind == LineKind::FuncEnter => Style::default().fg(mark_color),
+            _ => theme.content_style(content, row.kind).fg(mark_color),
+        };
+        write!(writer, "{}", content_style(style, color).apply(content))?;
+        if let (DiffKind::Changed, Some(right)) = (row.diff, row.right) {
+            let (_, value) = debug_info::split_value(debug_info::body(lines_b[right]));
+            write!(writer, " -> {}", content_style(style, color).apply(value))?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Converts a style of tui into ANSI escape sequences, which is empty if `color` is false.
+fn content_style(style: Style, color: bool) -> ContentStyle {
+    let mut content_style = ContentStyle::new();
+    if color {
+        let ansi_color = |color| match color {
+            Some(Color::Reset) | None => None,
+            Some(color) => Some(color.into()),
+        };
+        content_style.foreground_color = ansi_color(style.fg);
+        content_style.background_color = ansi_color(style.bg);
+        if style.add_modifier.contains(Modifier::BOLD) {
+            content_style.attributes.set(Attribute::Bold);
+        }
+    }
+    content_style
+}
@@ -0,0 +1,160 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::debug_info::CallTree;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Column by which statistics are sorted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StatsColumn {
+    FuncName,
+    NumCalls,
+    MaxDepth,
+    Callers,
+    Callees,
+    TotalTime,
+    SelfTime,
+}
+
+/// Statistics of calls of a function.
+#[derive(Clone, Debug, Default)]
+pub struct FuncStats {
+    pub func_name: String,
+    pub num_calls: usize,
+    pub max_depth: usize,
+    /// Locations where the function is defined, written as `file:line`.
+    pub locations: BTreeSet<String>,
+    pub threads: BTreeSet<String>,
+    pub callers: BTreeSet<String>,
+    pub callees: BTreeSet<String>,
+    /// Time spent in the calls including their callees.
+    pub total_time: Option<Duration>,
+    /// Time spent in the calls excluding their callees.
+    pub self_time: Option<Duration>,
+    /// Indices of the `func_enter` lines.
+    pub occurrences: Vec<usize>,
+}
+
+impl StatsColumn {
+    pub const ALL: [Self; 7] = [
+        Self::FuncName,
+        Self::NumCalls,
+        Self::MaxDepth,
+        Self::Callers,
+        Self::Callees,
+        Self::TotalTime,
+        Self::SelfTime,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::FuncName => "Function",
+            Self::NumCalls => "Calls",
+            Self::MaxDepth => "Depth",
+            Self::Callers => "Callers",
+            Self::Callees => "Callees",
+            Self::TotalTime => "Total",
+            Self::SelfTime => "Self",
+        }
+    }
+
+    /// Returns the next column, skipping times if they are not written.
+    pub fn next(self, has_time: bool) -> Self {
+        let next = Self::ALL
+            [(Self::ALL.iter().position(|column| *column == self).unwrap() + 1) % Self::ALL.len()];
+        if !has_time && matches!(next, Self::TotalTime | Self::SelfTime) {
+            Self::FuncName
+        } else {
+            next
+        }
+    }
+
+    fn compare(self, lhs: &FuncStats, rhs: &FuncStats) -> Ordering {
+        match self {
+            Self::FuncName => lhs.func_name.cmp(&rhs.func_name),
+            Self::NumCalls => lhs.num_calls.cmp(&rhs.num_calls),
+            Self::MaxDepth => lhs.max_depth.cmp(&rhs.max_depth),
+            Self::Callers => lhs.callers.len().cmp(&rhs.callers.len()),
+            Self::Callees => lhs.callees.len().cmp(&rhs.callees.len()),
+            Self::TotalTime => lhs.total_time.cmp(&rhs.total_time),
+            Self::SelfTime => lhs.self_time.cmp(&rhs.self_time),
+        }
+    }
+}
+
+/// Collects statistics of the functions.
+pub fn func_stats<'a>(
+    call_tree: &CallTree,
+    func_names: impl IntoIterator<Item = &'a str>,
+) -> Vec<FuncStats> {
+    let mut stats = func_names
+        .into_iter()
+        .map(|func_name| {
+            (
+                func_name,
+                FuncStats {
+                    func_name: func_name.to_string(),
+                    ..Default::default()
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    for (idx_call, call) in call_tree.calls.iter().enumerate() {
+        let func_stats = match stats.get_mut(call.func_name.as_str()) {
+            Some(func_stats) => func_stats,
+            None => continue,
+        };
+        func_stats.num_calls += 1;
+        func_stats.max_depth = func_stats.max_depth.max(call.depth);
+        func_stats.occurrences.push(call.enter_index);
+        func_stats.locations.insert(call.location.clone());
+        if let Some(thread) = &call.thread {
+            func_stats.threads.insert(thread.clone());
+        }
+        if let Some(parent) = call.parent {
+            func_stats
+                .callers
+                .insert(call_tree.calls[parent].func_name.clone());
+        }
+        for child in call.children.iter() {
+            func_stats
+                .callees
+                .insert(call_tree.calls[*child].func_name.clone());
+        }
+        // Time of a recursive call is already included in that of the outermost call.
+        let mut ancestor = call.parent;
+        while let Some(idx_ancestor) = ancestor {
+            if call_tree.calls[idx_ancestor].func_name == call.func_name {
+                break;
+            }
+            ancestor = call_tree.calls[idx_ancestor].parent;
+        }
+        if let (Some(duration), None) = (call.duration(), ancestor) {
+            *func_stats.total_time.get_or_insert(Duration::ZERO) += duration;
+        }
+        if let Some(self_time) = call_tree.self_time(idx_call) {
+            *func_stats.self_time.get_or_insert(Duration::ZERO) += self_time;
+        }
+    }
+    stats.into_values().collect()
+}
+
+/// Sorts statistics by the column, where numbers are sorted in descending order by default.
+pub fn sort(stats: &mut [FuncStats], column: StatsColumn, reverse: bool) {
+    stats.sort_by(|lhs, rhs| {
+        let ordering = match column {
+            StatsColumn::FuncName => column.compare(lhs, rhs),
+            _ => column.compare(rhs, lhs),
+        }
+        .then_with(|| lhs.func_name.cmp(&rhs.func_name));
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
@@ -17,20 +17,32 @@ pub mod handler;
 
 use self::app::App;
 use self::event::{Event, EventHandler};
-use self::handler::handle_key_events;
+use self::handler::keybinding::KeyBindings;
+use self::handler::{handle_key_events, handle_mouse_events};
 use self::tui::Tui;
+use crate::bookmark::Bookmarks;
+use crate::theme::Theme;
 use crate::visibility_info::VisibilityInfo;
-use ::tui::backend::CrosstermBackend;
+use ::tui::backend::{Backend, CrosstermBackend};
 use ::tui::Terminal;
 use anyhow::Result;
 use std::io;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
+#[allow(clippy::too_many_arguments)]
 pub fn activate(
     debug_info_file: PathBuf,
     visibility_info_file: PathBuf,
     debug_info: String,
     visibility_info: VisibilityInfo,
+    saved_visibility_info: VisibilityInfo,
+    visibility_info_modified: Option<SystemTime>,
+    bookmarks: Bookmarks,
+    key_bindings: KeyBindings,
+    theme: Theme,
+    follow: bool,
+    diff_file: Option<PathBuf>,
 ) -> Result<()> {
     // Create an application.
     let mut app = App::new(
@@ -38,7 +50,18 @@ pub fn activate(
         visibility_info_file,
         debug_info,
         visibility_info,
+        saved_visibility_info,
+        visibility_info_modified,
+        bookmarks,
+        key_bindings,
+        theme,
     );
+    if follow {
+        app.start_following()?;
+    }
+    if let Some(diff_file) = diff_file {
+        app.set_diff_file(diff_file)?;
+    }
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
@@ -46,22 +69,25 @@ pub fn activate(
     let events = EventHandler::new(250);
     let mut tui = Tui::new(terminal, events);
     tui.init()?;
+    let result = run(&mut tui, &mut app);
+
+    // Exit the user interface even if an error occurs, where files have been saved when quitting.
+    tui.exit()?;
+    result
+}
 
-    // Start the main loop.
+/// Runs the main loop until the application quits.
+fn run<B: Backend>(tui: &mut Tui<B>, app: &mut App) -> Result<()> {
     while app.running {
         // Render the user interface.
-        tui.draw(&mut app)?;
+        tui.draw(app)?;
         // Handle events.
         match tui.events.next()? {
-            Event::Tick => app.tick()?,
-            Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
-            Event::Mouse(_) => {}
+            Event::Tick => app.tick(),
+            Event::Key(key_event) => handle_key_events(key_event, app)?,
+            Event::Mouse(mouse_event) => handle_mouse_events(mouse_event, app)?,
             Event::Resize(_, _) => {}
         }
     }
-
-    // Exit the user interface.
-    tui.exit()?;
-
-    app.update_visibility_info_file()
+    Ok(())
 }
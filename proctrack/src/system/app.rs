@@ -3,25 +3,94 @@
 // Released under the MIT license.
 // see https://opensource.org/licenses/mit-license.php
 
-use super::handler::set_guidance;
-use crate::visibility_info::VisibilityInfo;
-use anyhow::Result;
+use super::handler;
+use super::handler::keybinding::{
+    CommonAction, ConflictAction, DiffAction, KeyBindings, NoteAction, RulesAction, SearchAction,
+    StatsAction, VisibilityAction,
+};
+use crate::bookmark::Bookmarks;
+use crate::debug_info::{self, CallTree, DisplayedLines, LineKind, LineOffsets};
+use crate::diff::{DiffKind, DiffRow, TraceDiff};
+use crate::stats::{self, FuncStats, StatsColumn};
+use crate::theme::Theme;
+use crate::visibility_info::{
+    self, PatternSyntax, ValueFilter, Visibility, VisibilityInfo, VisibilityMap, VisibilityRule,
+};
+use anyhow::{Context, Result};
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::Write as _;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tui::backend::Backend;
 use tui::layout::{Constraint, Direction, Layout, Rect};
-use tui::style::{Color, Style};
+use tui::style::{Color, Modifier, Style};
 use tui::terminal::Frame;
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use tui::widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Wrap};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum AppMode {
     ViewDebug,
     EditVisibility,
     SearchVisibility,
+    EditRules,
+    ViewValue,
+    ViewStats,
+    ViewBookmarks,
+    ViewDiff,
+    EditNote,
+    ResolveConflict,
+}
+
+/// Edit of visibility information which can be undone.
+#[derive(Debug)]
+enum VisibilityEdit {
+    Entries(Vec<EntryEdit>),
+    /// Rules before and after the edit.
+    Rules(Vec<VisibilityRule>, Vec<VisibilityRule>),
+    /// Value filters before and after the edit.
+    Values(ValueFilter, ValueFilter),
+}
+
+/// Visibility of an entry before and after an edit.
+#[derive(Debug)]
+struct EntryEdit {
+    func_name: String,
+    location: Option<String>,
+    old_visibility: Option<Visibility>,
+    new_visibility: Option<Visibility>,
+}
+
+/// Row of the Visibility pane.
+#[derive(Clone, Debug)]
+enum VisibilityRow {
+    /// Functions whose names start with the prefix ending with `::`.
+    Group {
+        prefix: String,
+        /// Length of the prefix of the parent group.
+        parent_len: usize,
+        depth: usize,
+        idx_entries: Vec<usize>,
+    },
+    Entry {
+        idx: usize,
+        /// Length of the prefix of the parent group.
+        parent_len: usize,
+        depth: usize,
+    },
+}
+
+impl VisibilityRow {
+    /// Returns the entries in the row.
+    fn idx_entries(&self) -> Vec<usize> {
+        match self {
+            Self::Group { idx_entries, .. } => idx_entries.clone(),
+            Self::Entry { idx, .. } => vec![*idx],
+        }
+    }
 }
 
 /// Application.
@@ -32,72 +101,347 @@ pub struct App {
 
     pub mode: AppMode,
 
-    guidance: String,
+    pub show_help: bool,
+    help_scroll: usize,
     pub search_string: String,
     search_regex: Regex,
     search_regex_error: String,
-    pub visibility_hash_map: HashMap<String, bool>,
-    regex_value_line: Regex,
+    pub visibility_hash_map: VisibilityMap,
 
     scroll: (u16, u16),
+    wrap: bool,
+    pub folded_calls: HashSet<usize>,
+    /// Lines displayed in the DebugInfo pane,
+    /// which are updated whenever debug_info, visibility or folds change.
+    displayed_lines: DisplayedLines,
+    line_offsets: LineOffsets,
+    popup_title: String,
+    popup_text: String,
+    popup_scroll: u16,
     pub idx_visibility: usize,
+    /// Number of jumps to occurrences of the selected functions in the Visibility pane.
+    idx_visibility_occurrence: usize,
+    /// `func_enter` lines of the calls of each entry in order,
+    /// which are updated whenever entries or the call tree change.
+    entry_occurrences: Vec<Vec<usize>>,
+    pub idx_rule: usize,
+    rules_error: String,
+    undo_stack: Vec<VisibilityEdit>,
+    redo_stack: Vec<VisibilityEdit>,
+
+    stats: Vec<FuncStats>,
+    stats_has_time: bool,
+    stats_column: StatsColumn,
+    stats_reverse: bool,
+    idx_stats: usize,
+    idx_occurrence: usize,
+
+    /// Is debug_info watched for appended lines?
+    pub follow: bool,
+    pub auto_scroll: bool,
+    /// Length of debug_info which has been read.
+    follow_offset: u64,
+
+    pub idx_bookmark: usize,
+    pub note_string: String,
+    note_index: usize,
+    note_return_mode: AppMode,
+    conflict_return_mode: AppMode,
+    /// Is the application quit after saving?
+    quit_after_save: bool,
+    /// Message shown in the status line until the next key is pressed.
+    pub status_message: String,
+
+    /// Debug information compared in the diff mode.
+    pub diff_file: Option<PathBuf>,
+    diff_debug_info: String,
+    diff_call_tree: CallTree,
+    trace_diff: TraceDiff,
+    diff_scroll: usize,
 
     pub debug_info_file: PathBuf,
     pub visibility_info_file: PathBuf,
     pub debug_info: String,
     pub visibility_info: VisibilityInfo,
+    /// Contents of the visibility_info file when it was read or written last.
+    saved_visibility_info: VisibilityInfo,
+    /// Modification time of the visibility_info file when it was read or written last.
+    visibility_info_modified: Option<SystemTime>,
+    pub call_tree: CallTree,
+    pub bookmarks_file: PathBuf,
+    pub bookmarks: Bookmarks,
+    pub key_bindings: KeyBindings,
+    pub theme: Theme,
+
+    chunk_debug_info: Rect,
+    chunk_visibility_info: Rect,
+    chunk_rules: Rect,
+    chunk_stats: Rect,
+    chunk_bookmarks: Rect,
+    chunk_diff: Rect,
 
     string_buffer: String,
 }
 
 impl App {
     /// Constructs a new instance of [`App`].
+    ///
+    /// `saved_visibility_info` is the contents of the visibility_info file as read,
+    /// and `visibility_info_modified` is the time when it was modified before it was read.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         debug_info_file: PathBuf,
         visibility_info_file: PathBuf,
         debug_info: String,
         visibility_info: VisibilityInfo,
+        saved_visibility_info: VisibilityInfo,
+        visibility_info_modified: Option<SystemTime>,
+        bookmarks: Bookmarks,
+        key_bindings: KeyBindings,
+        theme: Theme,
     ) -> Self {
+        let bookmarks_file = Bookmarks::file_path(&visibility_info_file);
         let mut app = Self {
             running: true,
             mode: AppMode::ViewDebug,
-            guidance: String::new(),
+            show_help: false,
+            help_scroll: 0,
             search_string: String::new(),
             search_regex: Regex::new("").unwrap(),
             search_regex_error: String::new(),
             visibility_hash_map: visibility_info.clone().into(),
-            regex_value_line: Regex::new(r"\[DEBUG:value\(.*\)] ").unwrap(),
             scroll: (0, 0),
+            wrap: false,
+            folded_calls: HashSet::new(),
+            displayed_lines: DisplayedLines::default(),
+            line_offsets: LineOffsets::default(),
+            popup_title: String::new(),
+            popup_text: String::new(),
+            popup_scroll: 0,
             idx_visibility: 0,
+            idx_visibility_occurrence: 0,
+            entry_occurrences: Vec::new(),
+            idx_rule: 0,
+            rules_error: String::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            stats: Vec::new(),
+            stats_has_time: false,
+            stats_column: StatsColumn::NumCalls,
+            stats_reverse: false,
+            idx_stats: 0,
+            idx_occurrence: 0,
+            follow: false,
+            auto_scroll: false,
+            follow_offset: 0,
+            idx_bookmark: 0,
+            note_string: String::new(),
+            note_index: 0,
+            note_return_mode: AppMode::ViewDebug,
+            conflict_return_mode: AppMode::ViewDebug,
+            quit_after_save: false,
+            status_message: String::new(),
+            diff_file: None,
+            diff_debug_info: String::new(),
+            diff_call_tree: CallTree::default(),
+            trace_diff: TraceDiff::default(),
+            diff_scroll: 0,
             debug_info_file,
             visibility_info_file,
+            call_tree: CallTree::new(&debug_info),
             debug_info,
             visibility_info,
+            saved_visibility_info,
+            visibility_info_modified,
+            bookmarks_file,
+            bookmarks,
+            key_bindings,
+            theme,
+            chunk_debug_info: Rect::default(),
+            chunk_visibility_info: Rect::default(),
+            chunk_rules: Rect::default(),
+            chunk_stats: Rect::default(),
+            chunk_bookmarks: Rect::default(),
+            chunk_diff: Rect::default(),
             string_buffer: String::new(),
         };
-        set_guidance(app.mode, &mut app.guidance);
+        app.update_line_offsets();
+        app.update_displayed_lines();
+        app.update_entry_occurrences(0);
         app
     }
 
     /// Handles the tick event of the terminal.
-    pub fn tick(&mut self) -> Result<()> {
+    ///
+    /// An error in reading debug_info, e.g. when the file is removed, is shown in the status line.
+    pub fn tick(&mut self) {
+        if self.follow {
+            if let Err(err) = self.read_appended_debug_info() {
+                self.status_message = format!("Error: {:#}", err);
+            }
+        }
+    }
+
+    /// Starts watching debug_info for appended lines.
+    pub fn start_following(&mut self) -> Result<()> {
+        self.follow = true;
+        self.auto_scroll = true;
+        self.drop_incomplete_line();
+        self.update_line_offsets();
+        self.call_tree = CallTree::new(&self.debug_info);
+        self.visibility_info =
+            std::mem::take(&mut self.visibility_info).update_by_debug_info(&self.debug_info)?;
+        self.visibility_hash_map = self.visibility_info.clone().into();
+        self.update_displayed_lines();
+        self.update_entry_occurrences(0);
+        Ok(())
+    }
+
+    pub fn toggle_auto_scroll(&mut self) {
+        if self.follow {
+            self.auto_scroll ^= true;
+        }
+    }
+
+    /// Re-reads debug_info and visibility_info, which resets folds.
+    pub fn reload(&mut self) -> Result<()> {
+        self.debug_info = fs::read_to_string(&self.debug_info_file)
+            .with_context(|| format!("failed to read {}", self.debug_info_file.display()))?;
+        if self.follow {
+            self.drop_incomplete_line();
+        }
+        self.update_line_offsets();
+        self.call_tree = CallTree::new(&self.debug_info);
+        let profile = self.visibility_info.profile().map(str::to_string);
+        self.visibility_info_modified = visibility_info::modified_time(&self.visibility_info_file);
+        let mut visibility_info = VisibilityInfo::try_new(&self.visibility_info_file)?;
+        self.saved_visibility_info = visibility_info.clone();
+        visibility_info.select_profile(profile.as_deref());
+        self.visibility_info = visibility_info.update_by_debug_info(&self.debug_info)?;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.visibility_hash_map = self.visibility_info.clone().into();
+        self.folded_calls.clear();
+        self.update_displayed_lines();
+        self.update_entry_occurrences(0);
+        self.bookmarks = std::mem::take(&mut self.bookmarks).update_by_debug_info(&self.debug_info);
+        self.scroll.0 = std::cmp::min(
+            self.scroll.0 as usize,
+            self.num_displayed_debug_info_lines().saturating_sub(1),
+        ) as u16;
+        if self.mode == AppMode::ViewStats {
+            self.update_stats();
+        }
+        if self.diff_file.is_some() {
+            self.read_diff_debug_info()?;
+        }
+        Ok(())
+    }
+
+    /// Drops the last line which is still being written.
+    fn drop_incomplete_line(&mut self) {
+        let len = self.debug_info.rfind('\n').map_or(0, |idx| idx + 1);
+        self.debug_info.truncate(len);
+        self.follow_offset = len as u64;
+    }
+
+    /// Reads complete lines appended to debug_info since the last read.
+    ///
+    /// If debug_info gets shorter, it is regarded as rewritten and read again from the start,
+    /// where visibility_info and its history are kept.
+    fn read_appended_debug_info(&mut self) -> Result<()> {
+        let mut file = File::open(&self.debug_info_file)
+            .with_context(|| format!("failed to open {}", self.debug_info_file.display()))?;
+        let len = file
+            .metadata()
+            .with_context(|| {
+                format!(
+                    "failed to get metadata of {}",
+                    self.debug_info_file.display()
+                )
+            })?
+            .len();
+        if len == self.follow_offset {
+            return Ok(());
+        }
+        let rewritten = len < self.follow_offset;
+        if rewritten {
+            self.debug_info.clear();
+            self.follow_offset = 0;
+            self.line_offsets = LineOffsets::default();
+            self.call_tree = CallTree::default();
+            self.displayed_lines = DisplayedLines::default();
+            self.folded_calls.clear();
+        }
+        let mut appended = Vec::new();
+        file.seek(SeekFrom::Start(self.follow_offset))
+            .and_then(|_| file.read_to_end(&mut appended))
+            .with_context(|| format!("failed to read {}", self.debug_info_file.display()))?;
+        let appended_len = appended
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |idx| idx + 1);
+        let appended = String::from_utf8_lossy(&appended[..appended_len]);
+        let start = self.debug_info.len();
+        let num_calls = self.call_tree.calls.len();
+        self.follow_offset += appended_len as u64;
+        self.debug_info.push_str(&appended);
+        self.line_offsets.extend(&self.debug_info, start);
+        self.call_tree.extend(&appended);
+        if self
+            .visibility_info
+            .extend_by_call_tree(&self.call_tree, num_calls)
+        {
+            self.visibility_hash_map = self.visibility_info.clone().into();
+            self.update_entry_occurrences(0);
+        } else {
+            self.update_entry_occurrences(num_calls);
+        }
+        self.displayed_lines
+            .extend(&appended, &self.visibility_hash_map, &self.folded_calls);
+        if rewritten {
+            self.bookmarks =
+                std::mem::take(&mut self.bookmarks).update_by_debug_info(&self.debug_info);
+            self.scroll_down(0);
+        } else if appended.is_empty() {
+            return Ok(());
+        }
+        if self.mode == AppMode::ViewStats {
+            self.update_stats();
+        }
+        if self.mode == AppMode::ViewDiff {
+            self.update_diff();
+        }
+        if self.auto_scroll {
+            self.scroll_to_last_page();
+        }
         Ok(())
     }
 
     /// Renders the user interface widgets.
     pub fn render<B: Backend>(&mut self, frame: &mut Frame<'_, B>) {
-        let guidance_height = 4;
+        let status_line_height = 1;
         let search_box_height = 3;
-        let visibility_info_width = match self.mode {
-            AppMode::ViewDebug => 0,
+        let stats_detail_height = 7;
+        let note_box_height = 3;
+        // The side pane is kept while writing a note or asking how to save.
+        let mut side_pane_mode = self.mode;
+        if side_pane_mode == AppMode::ResolveConflict {
+            side_pane_mode = self.conflict_return_mode;
+        }
+        if side_pane_mode == AppMode::EditNote {
+            side_pane_mode = self.note_return_mode;
+        }
+        let side_pane_width = match side_pane_mode {
+            AppMode::ViewDebug | AppMode::ViewValue | AppMode::ViewDiff => 0,
             _ => frame.size().width / 2,
         };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints(
                 [
-                    Constraint::Length(guidance_height),
-                    Constraint::Length(frame.size().height - guidance_height),
+                    Constraint::Length(status_line_height),
+                    Constraint::Length(frame.size().height - status_line_height),
                 ]
                 .as_ref(),
             )
@@ -106,8 +450,8 @@ impl App {
             .direction(Direction::Horizontal)
             .constraints(
                 [
-                    Constraint::Length(frame.size().width - visibility_info_width),
-                    Constraint::Length(visibility_info_width),
+                    Constraint::Length(frame.size().width - side_pane_width),
+                    Constraint::Length(side_pane_width),
                 ]
                 .as_ref(),
             )
@@ -122,97 +466,416 @@ impl App {
                 .as_ref(),
             )
             .split(chunks_1[1]);
+        let chunks_1_2 = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(stats_detail_height)].as_ref())
+            .split(chunks_1[1]);
 
-        let chunk_guidance = chunks[0];
+        let chunk_status_line = chunks[0];
         let chunk_debug_info = chunks_1[0];
         let chunk_search_box = chunks_1_1[0];
         let chunk_visibility_info = chunks_1_1[1];
+        let chunk_stats = chunks_1_2[0];
+        let chunk_stats_detail = chunks_1_2[1];
+        let chunk_bookmarks = chunks_1[1];
+        let chunk_note_box = Rect {
+            y: chunk_debug_info.bottom().saturating_sub(note_box_height),
+            height: std::cmp::min(note_box_height, chunk_debug_info.height),
+            ..chunk_debug_info
+        };
+        self.chunk_debug_info = chunk_debug_info;
+        self.chunk_visibility_info = Rect::default();
+        self.chunk_rules = Rect::default();
+        self.chunk_stats = Rect::default();
+        self.chunk_bookmarks = Rect::default();
+        self.chunk_diff = Rect::default();
 
-        self.render_guidance(frame, chunk_guidance);
-        self.render_debug_info(frame, chunk_debug_info);
-        self.render_search_box(frame, chunk_search_box);
-        self.render_visibility_info(frame, chunk_visibility_info);
+        self.render_status_line(frame, chunk_status_line);
+        if self.mode == AppMode::ViewDiff {
+            self.chunk_debug_info = Rect::default();
+            self.chunk_diff = chunk_debug_info;
+            self.render_diff(frame, chunk_debug_info);
+        } else {
+            self.render_debug_info(frame, chunk_debug_info);
+        }
+        match side_pane_mode {
+            AppMode::ViewStats => {
+                self.chunk_stats = chunk_stats;
+                self.render_stats(frame, chunk_stats);
+                self.render_stats_detail(frame, chunk_stats_detail);
+            }
+            AppMode::ViewBookmarks => {
+                self.chunk_bookmarks = chunk_bookmarks;
+                self.render_bookmarks(frame, chunk_bookmarks);
+            }
+            AppMode::EditVisibility | AppMode::SearchVisibility => {
+                self.chunk_visibility_info = chunk_visibility_info;
+                self.render_search_box(frame, chunk_search_box);
+                self.render_visibility_info(frame, chunk_visibility_info);
+            }
+            AppMode::EditRules => {
+                self.chunk_rules = chunks_1[1];
+                self.render_rules(frame, chunks_1[1]);
+            }
+            _ => (),
+        }
+        if self.mode == AppMode::EditNote {
+            self.render_note_box(frame, chunk_note_box);
+        }
+        if self.mode == AppMode::ResolveConflict {
+            self.render_conflict(frame, frame.size());
+        }
+        if self.mode == AppMode::ViewValue {
+            self.render_popup(frame, frame.size());
+        }
+        if self.show_help {
+            self.render_help(frame, frame.size());
+        }
     }
 
-    fn render_guidance<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+    fn render_status_line<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        let mode_name = match self.mode {
+            AppMode::ViewDebug => "View",
+            AppMode::EditVisibility => "Visibility",
+            AppMode::SearchVisibility => "Search",
+            AppMode::EditRules => "Rules",
+            AppMode::ViewValue => "Value",
+            AppMode::ViewStats => "Stats",
+            AppMode::ViewBookmarks => "Bookmarks",
+            AppMode::ViewDiff => "Diff",
+            AppMode::EditNote => "Note",
+            AppMode::ResolveConflict => "Conflict",
+        };
+        let hint = match self.mode {
+            AppMode::SearchVisibility => format!(
+                "Finish searching [{}]",
+                self.key_bindings.search.keys_string(SearchAction::EditMode)
+            ),
+            AppMode::EditNote => format!(
+                "Finish writing [{}]",
+                self.key_bindings.note.keys_string(NoteAction::Finish)
+            ),
+            _ => format!(
+                "Help [{}]",
+                self.key_bindings.common.keys_string(CommonAction::Help)
+            ),
+        };
+        if self.mode == AppMode::ViewDiff {
+            frame.render_widget(
+                Paragraph::new(Spans::from(vec![
+                    Span::styled(
+                        format!(" {} ", mode_name),
+                        Style::default().add_modifier(Modifier::REVERSED),
+                    ),
+                    Span::raw(format!(
+                        " {} {}/{} ",
+                        self.diff_file.as_deref().unwrap_or(Path::new("")).display(),
+                        std::cmp::min(self.diff_scroll + 1, self.trace_diff.rows.len()),
+                        self.trace_diff.rows.len(),
+                    )),
+                    match self.trace_diff.first_divergence {
+                        Some(i_row) => Span::styled(
+                            format!("[First divergence: {}] ", i_row + 1),
+                            Style::default().fg(self.theme.changed),
+                        ),
+                        None if self.trace_diff.is_same() => Span::raw("[No difference] "),
+                        None => Span::raw("[No divergence in calls] "),
+                    },
+                    self.status_message_span(),
+                    Span::styled(hint, Style::default().fg(self.theme.inactive)),
+                ])),
+                chunk,
+            );
+            return;
+        }
+        let num_displayed_debug_info_lines = self.num_displayed_debug_info_lines();
         frame.render_widget(
-            Paragraph::new(self.guidance.as_ref()).block(Block::default().borders(Borders::ALL)),
+            Paragraph::new(Spans::from(vec![
+                Span::styled(
+                    format!(" {} ", mode_name),
+                    Style::default().add_modifier(Modifier::REVERSED),
+                ),
+                Span::raw(format!(
+                    " {} {}/{} ",
+                    self.debug_info_file.display(),
+                    std::cmp::min(self.scroll.0 as usize + 1, num_displayed_debug_info_lines),
+                    num_displayed_debug_info_lines,
+                )),
+                match self.visibility_info.profile() {
+                    Some(profile) => Span::styled(
+                        format!("[Profile: {}] ", profile),
+                        Style::default().fg(self.theme.func),
+                    ),
+                    None => Span::raw(""),
+                },
+                if self.visibility_info.values.hide_all {
+                    Span::styled("[Values: hidden] ", Style::default().fg(self.theme.value))
+                } else if !self.visibility_info.values.shows_all() {
+                    Span::styled("[Values: filtered] ", Style::default().fg(self.theme.value))
+                } else {
+                    Span::raw("")
+                },
+                if self.follow {
+                    Span::styled(
+                        if self.auto_scroll {
+                            "[Follow, Auto-scroll] "
+                        } else {
+                            "[Follow] "
+                        },
+                        Style::default().fg(self.theme.value),
+                    )
+                } else {
+                    Span::raw("")
+                },
+                self.status_message_span(),
+                Span::styled(hint, Style::default().fg(self.theme.inactive)),
+            ])),
             chunk,
         );
     }
 
+    fn status_message_span(&self) -> Span<'_> {
+        if self.status_message.is_empty() {
+            Span::raw("")
+        } else {
+            Span::styled(
+                format!("[{}] ", self.status_message),
+                Style::default().fg(self.theme.value),
+            )
+        }
+    }
+
     fn render_debug_info<B: Backend>(&mut self, frame: &mut Frame<B>, chunk: Rect) {
-        macro_rules! write_depth_representation {
-            ($string_buffer:expr,$line_number:expr, $depth:expr) => {
-                write!($string_buffer, "{:>3} ", $line_number).unwrap();
-                for _ in 0..$depth {
-                    write!($string_buffer, "| ").unwrap();
-                }
+        let block = Block::default().borders(Borders::ALL).title("DebugInfo");
+        let inner_chunk = block.inner(chunk);
+        frame.render_widget(block, chunk);
+        let breadcrumb_height = std::cmp::min(1, inner_chunk.height);
+        let chunk_breadcrumb = Rect {
+            height: breadcrumb_height,
+            ..inner_chunk
+        };
+        let chunk_lines = Rect {
+            y: inner_chunk.y + breadcrumb_height,
+            height: inner_chunk.height - breadcrumb_height,
+            ..inner_chunk
+        };
+        self.render_breadcrumb(frame, chunk_breadcrumb);
+
+        let mut displayed_debug_info = Vec::new();
+        for (i_displayed, displayed_line) in self
+            .displayed_lines
+            .iter()
+            .enumerate()
+            .skip(self.scroll.0 as usize)
+            .take(chunk_lines.height as usize)
+        {
+            let line = self
+                .line_offsets
+                .line(&self.debug_info, displayed_line.index)
+                .unwrap_or_default();
+            let bookmark = self.bookmarks.of_line(displayed_line.index);
+            self.string_buffer.clear();
+            for _ in 0..displayed_line.depth {
+                write!(self.string_buffer, "| ").unwrap();
+            }
+            let content = debug_info::content(line, displayed_line.kind);
+            let content_style = self.theme.content_style(content, displayed_line.kind);
+            let content = if self.wrap {
+                content
+            } else {
+                content
+                    .char_indices()
+                    .nth(self.scroll.1 as usize)
+                    .map_or("", |(idx, _)| &content[idx..])
             };
+            displayed_debug_info.push(Spans::from(vec![
+                Span::raw(if i_displayed == self.scroll.0 as usize {
+                    ">"
+                } else {
+                    " "
+                }),
+                Span::styled(
+                    format!("{:>3}", i_displayed + 1),
+                    Style::default().fg(self.theme.depth),
+                ),
+                Span::styled(
+                    if bookmark.is_some() { "*" } else { " " },
+                    Style::default().fg(self.theme.bookmark),
+                ),
+                Span::styled(
+                    self.string_buffer.clone(),
+                    Style::default().fg(self.theme.depth),
+                ),
+                Span::styled(content, content_style),
+                if displayed_line.folded {
+                    Span::styled(" {...}", Style::default().fg(self.theme.depth))
+                } else {
+                    Span::raw("")
+                },
+                match bookmark {
+                    Some(bookmark) if !bookmark.note.is_empty() => Span::styled(
+                        format!("  # {}", bookmark.note),
+                        Style::default().fg(self.theme.bookmark),
+                    ),
+                    _ => Span::raw(""),
+                },
+            ]));
         }
-        let mut displayed_debug_info = Vec::new();
-        let mut display = true;
-        let mut depth = 0;
-        let mut display_depth = depth;
-        let mut displayed_line_number = 0;
-        for line in self.debug_info.lines() {
-            if line.starts_with("[DEBUG:func_enter") {
-                let func_name = line.split_ascii_whitespace().last().unwrap();
-                if !self
+        let paragraph = Paragraph::new(displayed_debug_info);
+        frame.render_widget(
+            if self.wrap {
+                paragraph.wrap(Wrap { trim: false })
+            } else {
+                paragraph
+            },
+            chunk_lines,
+        );
+    }
+
+    /// Renders the calls which the line at the top is inside.
+    ///
+    /// Calls hidden by visibility information are put in parentheses.
+    fn render_breadcrumb<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        const SEPARATOR: &str = " > ";
+        const OMISSION: &str = "...";
+        let stack = match self.displayed_lines.get(self.scroll.0 as usize) {
+            Some(displayed_line) => self.call_tree.stack(displayed_line.index),
+            None => Vec::new(),
+        };
+        let mut crumbs = stack
+            .into_iter()
+            .map(|idx_call| {
+                let call = &self.call_tree.calls[idx_call];
+                let func_name = call.func_name.as_str();
+                if self
                     .visibility_hash_map
-                    .get(func_name)
-                    .map_or(false, |visibility| *visibility)
+                    .get(func_name, &call.location)
+                    .is_some_and(|visibility| visibility.shows_frame())
                 {
-                    display = false;
-                } else if display {
-                    display_depth = depth
-                }
-                if display {
-                    displayed_line_number += 1;
-                    self.string_buffer.clear();
-                    write_depth_representation!(
-                        &mut self.string_buffer,
-                        displayed_line_number,
-                        depth
-                    );
-                    displayed_debug_info.push(Spans::from(vec![
-                        Span::raw(" "),
-                        Span::styled(
-                            self.string_buffer.clone(),
-                            Style::default().fg(Color::DarkGray),
-                        ),
-                        Span::raw(func_name),
-                    ]));
-                }
-                depth += 1;
-            } else if line.starts_with("[DEBUG:func_exit") {
-                depth -= 1;
-                if display_depth == depth {
-                    display = true;
-                }
-            } else if display && line.starts_with("[DEBUG:value") {
-                displayed_line_number += 1;
-                self.string_buffer.clear();
-                write_depth_representation!(&mut self.string_buffer, displayed_line_number, depth);
-                displayed_debug_info.push(Spans::from(vec![
-                    Span::raw(" "),
-                    Span::styled(
-                        self.string_buffer.clone(),
-                        Style::default().fg(Color::DarkGray),
-                    ),
+                    Span::styled(func_name, self.theme.func_style(func_name))
+                } else {
                     Span::styled(
-                        format!("{}", self.regex_value_line.replace(line, ""),),
-                        Style::default().fg(Color::Cyan),
-                    ),
-                ]));
+                        format!("({})", func_name),
+                        Style::default().fg(self.theme.hidden),
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        // Omit outer calls so that the innermost one can be seen.
+        let width = |crumbs: &[Span]| {
+            crumbs
+                .iter()
+                .map(|crumb| crumb.width() + SEPARATOR.len())
+                .sum::<usize>()
+        };
+        let mut omitted = false;
+        while crumbs.len() > 1 && width(&crumbs) + OMISSION.len() > chunk.width as usize {
+            crumbs.remove(0);
+            omitted = true;
+        }
+        let mut spans = Vec::with_capacity(2 * crumbs.len() + 1);
+        if omitted {
+            spans.push(Span::styled(
+                OMISSION,
+                Style::default().fg(self.theme.depth),
+            ));
+        }
+        for (i_crumb, crumb) in crumbs.into_iter().enumerate() {
+            if omitted || i_crumb > 0 {
+                spans.push(Span::styled(
+                    SEPARATOR,
+                    Style::default().fg(self.theme.depth),
+                ));
             }
+            spans.push(crumb);
         }
-        frame.render_widget(
-            Paragraph::new(displayed_debug_info)
-                .block(Block::default().borders(Borders::ALL).title("DebugInfo"))
-                .scroll(self.scroll),
-            chunk,
+        frame.render_widget(Paragraph::new(Spans::from(spans)), chunk);
+    }
+
+    /// Renders the two traces side by side.
+    fn render_diff<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunk);
+        let diff_file = self.diff_file.as_deref().unwrap_or(Path::new(""));
+        self.render_diff_side(
+            frame,
+            chunks[0],
+            &self.debug_info_file.display().to_string(),
+            &self.debug_info,
+            |row| row.left,
         );
+        self.render_diff_side(
+            frame,
+            chunks[1],
+            &diff_file.display().to_string(),
+            &self.diff_debug_info,
+            |row| row.right,
+        );
+    }
+
+    fn render_diff_side<B: Backend>(
+        &self,
+        frame: &mut Frame<B>,
+        chunk: Rect,
+        title: &str,
+        debug_info: &str,
+        side: impl Fn(&DiffRow) -> Option<usize>,
+    ) {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let inner_chunk = block.inner(chunk);
+        frame.render_widget(block, chunk);
+        let lines = debug_info.lines().collect::<Vec<_>>();
+        let mut displayed_rows = Vec::new();
+        for (i_row, row) in self
+            .trace_diff
+            .rows
+            .iter()
+            .enumerate()
+            .skip(self.diff_scroll)
+            .take(inner_chunk.height as usize)
+        {
+            let index = match side(row) {
+                Some(index) => index,
+                None => {
+                    displayed_rows.push(Spans::default());
+                    continue;
+                }
+            };
+            let (mark, mark_color) = match row.diff {
+                DiffKind::Same => (" ", self.theme.depth),
+                DiffKind::Removed => ("-", self.theme.removed),
+                DiffKind::Added => ("+", self.theme.added),
+                DiffKind::Changed => ("!", self.theme.changed),
+            };
+            let mark_style = if Some(i_row) == self.trace_diff.first_divergence {
+                Style::default()
+                    .fg(mark_color)
+                    .add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default().fg(mark_color)
+            };
+            let content = debug_info::content(lines[index], row.kind);
+            let content_style = match row.diff {
+                DiffKind::Same => self.theme.content_style(content, row.kind),
+                _ => Style::default().fg(mark_color),
+            };
+            displayed_rows.push(Spans::from(vec![
+                Span::raw(if i_row == self.diff_scroll { ">" } else { " " }),
+                Span::styled(
+                    format!("{:>4}", index + 1),
+                    Style::default().fg(self.theme.depth),
+                ),
+                Span::styled(mark, mark_style),
+                Span::styled(
+                    "| ".repeat(row.depth),
+                    Style::default().fg(self.theme.depth),
+                ),
+                Span::styled(content, content_style),
+            ]));
+        }
+        frame.render_widget(Paragraph::new(displayed_rows), inner_chunk);
     }
 
     fn render_search_box<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
@@ -224,13 +887,13 @@ impl App {
                     if self.mode == AppMode::SearchVisibility {
                         Style::default()
                     } else {
-                        Style::default().fg(Color::DarkGray)
+                        Style::default().fg(self.theme.inactive)
                     },
                 ),
                 Span::styled(
                     " ",
                     if self.mode == AppMode::SearchVisibility {
-                        Style::default().bg(Color::Gray)
+                        Style::default().bg(self.theme.cursor)
                     } else {
                         Style::default()
                     },
@@ -240,7 +903,7 @@ impl App {
                 } else {
                     Span::styled(
                         format!("   [Error: {}]", self.search_regex_error),
-                        Style::default().fg(Color::Red),
+                        Style::default().fg(self.theme.error),
                     )
                 },
             ])])
@@ -252,7 +915,7 @@ impl App {
                         Style::default().fg(if self.mode == AppMode::SearchVisibility {
                             Color::Reset
                         } else {
-                            Color::DarkGray
+                            self.theme.inactive
                         }),
                     ),
             ),
@@ -261,99 +924,1172 @@ impl App {
     }
 
     fn render_visibility_info<B: Backend>(&mut self, frame: &mut Frame<B>, chunk: Rect) {
+        let rows = self
+            .visibility_rows()
+            .into_iter()
+            .map(|visibility_row| {
+                // Groups count the calls of all the functions in them.
+                let idx_entries = visibility_row.idx_entries();
+                let calls = Cell::from(
+                    idx_entries
+                        .iter()
+                        .map(|idx| self.entry_occurrences[*idx].len())
+                        .sum::<usize>()
+                        .to_string(),
+                );
+                let first_line = Cell::from(
+                    idx_entries
+                        .iter()
+                        .filter_map(|idx| self.entry_occurrences[*idx].first())
+                        .min()
+                        .map_or(String::new(), |index| (index + 1).to_string()),
+                )
+                .style(Style::default().fg(self.theme.depth));
+                match visibility_row {
+                    VisibilityRow::Group {
+                        prefix,
+                        parent_len,
+                        depth,
+                        idx_entries,
+                    } => {
+                        let num_shown = idx_entries
+                            .iter()
+                            .filter(|idx| {
+                                self.visibility_info
+                                    .visibility_of_entry(&self.visibility_info[**idx])
+                                    .shows_frame()
+                            })
+                            .count();
+                        // Groups where only some functions are shown are colored distinctly.
+                        let style = if num_shown == idx_entries.len() {
+                            Style::default().add_modifier(Modifier::BOLD)
+                        } else if num_shown == 0 {
+                            Style::default().fg(self.theme.hidden)
+                        } else {
+                            Style::default()
+                                .fg(self.theme.partial)
+                                .add_modifier(Modifier::BOLD)
+                        };
+                        Row::new(vec![
+                            Cell::from(Spans::from(vec![
+                                Span::raw("  ".repeat(depth)),
+                                Span::styled(prefix[parent_len..].to_string(), style),
+                                Span::styled(
+                                    format!(" [{}/{}]", num_shown, idx_entries.len()),
+                                    Style::default().fg(self.theme.depth),
+                                ),
+                            ])),
+                            Cell::from(""),
+                            calls,
+                            first_line,
+                        ])
+                    }
+                    VisibilityRow::Entry {
+                        idx,
+                        parent_len,
+                        depth,
+                    } => {
+                        let entry = &self.visibility_info[idx];
+                        let visibility = self.visibility_info.visibility_of_entry(entry);
+                        let style = if visibility.shows_frame() {
+                            self.theme.func_style(&entry.func_name)
+                        } else {
+                            Style::default().fg(self.theme.hidden)
+                        };
+                        // Visibility decided by a rule is followed by the pattern.
+                        let rule = self.visibility_info.rule_of_entry(entry);
+                        // An entry without a location applies to all the locations.
+                        let location = match &entry.location {
+                            Some(location) => Cell::from(location.as_str()),
+                            None => {
+                                Cell::from("(all)").style(Style::default().fg(self.theme.inactive))
+                            }
+                        };
+                        // Modes other than showing and hiding are written after the name.
+                        Row::new(vec![
+                            Cell::from(Spans::from(vec![
+                                Span::raw("  ".repeat(depth)),
+                                Span::styled(&entry.func_name[parent_len..], style),
+                                match visibility {
+                                    Visibility::HideFrame | Visibility::Collapse => Span::styled(
+                                        format!(" ({})", visibility.name()),
+                                        Style::default().fg(self.theme.depth),
+                                    ),
+                                    _ => Span::raw(""),
+                                },
+                                if entry.stale {
+                                    Span::styled(
+                                        " (stale)",
+                                        Style::default().fg(self.theme.inactive),
+                                    )
+                                } else {
+                                    Span::raw("")
+                                },
+                                match rule {
+                                    Some(rule) => Span::styled(
+                                        format!("  [{}]", rule.pattern),
+                                        Style::default().fg(self.theme.inactive),
+                                    ),
+                                    None => Span::raw(""),
+                                },
+                            ])),
+                            location,
+                            calls,
+                            first_line,
+                        ])
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let location_width = self
+            .visibility_info
+            .iter()
+            .map(|entry| entry.location.as_ref().map_or(5, String::len))
+            .max()
+            .unwrap_or_default()
+            .max("Location".len())
+            .min(chunk.width as usize / 3) as u16;
+        // The rest of the borders, the highlight symbol and the other columns is for names.
+        let widths = [
+            Constraint::Length(
+                chunk
+                    .width
+                    .saturating_sub(2 + 3 + location_width + 6 + 6 + 3),
+            ),
+            Constraint::Length(location_width),
+            Constraint::Length(6),
+            Constraint::Length(6),
+        ];
+        let table = Table::new(rows)
+            .header(Row::new(vec!["Function", "Location", "Calls", "Line"]))
+            .widths(&widths)
+            .highlight_symbol(" > ");
+        let mut table_state = TableState::default();
+        table_state.select(Some(self.idx_visibility));
+        frame.render_stateful_widget(
+            table.block(Block::default().borders(Borders::ALL).title("Visibility")),
+            chunk,
+            &mut table_state,
+        );
+    }
+
+    fn render_rules<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
         let rows = self
             .visibility_info
+            .rules
             .iter()
-            .filter_map(|entry| {
-                if self.search_regex.is_match(&entry.func_name) {
-                    let style = if entry.visibility {
+            .map(|rule| {
+                let num_matches = self
+                    .visibility_info
+                    .iter()
+                    .filter(|entry| entry.visibility.is_none() && rule.is_match(&entry.func_name))
+                    .count();
+                Row::new(vec![
+                    Cell::from(rule.pattern.as_str()),
+                    Cell::from(match rule.syntax {
+                        PatternSyntax::Glob => "glob",
+                        PatternSyntax::Regex => "regex",
+                    }),
+                    Cell::from(rule.visibility.name()).style(if rule.visibility.shows_frame() {
                         Style::default()
                     } else {
-                        Style::default().fg(Color::DarkGray)
-                    };
-                    Some(Row::new(vec![
-                        Cell::from(entry.func_name.as_ref()).style(style)
-                    ]))
+                        Style::default().fg(self.theme.hidden)
+                    }),
+                    Cell::from(num_matches.to_string()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        // The rest of the borders, the highlight symbol and the other columns is for patterns.
+        let widths = [
+            Constraint::Length(chunk.width.saturating_sub(2 + 3 + 6 + 10 + 7 + 3)),
+            Constraint::Length(6),
+            Constraint::Length(10),
+            Constraint::Length(7),
+        ];
+        let table = Table::new(rows)
+            .header(Row::new(vec!["Pattern", "Syntax", "Visibility", "Matches"]))
+            .widths(&widths)
+            .highlight_symbol(" > ");
+        let mut table_state = TableState::default();
+        table_state.select(Some(self.idx_rule));
+        let title = if self.rules_error.is_empty() {
+            Spans::from("Rules")
+        } else {
+            Spans::from(vec![
+                Span::raw("Rules "),
+                Span::styled(
+                    format!("[Error: {}]", self.rules_error),
+                    Style::default().fg(self.theme.error),
+                ),
+            ])
+        };
+        frame.render_stateful_widget(
+            table.block(Block::default().borders(Borders::ALL).title(title)),
+            chunk,
+            &mut table_state,
+        );
+    }
+
+    fn render_bookmarks<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        let rows = self
+            .bookmarks
+            .iter()
+            .map(|bookmark| {
+                let (text, style) = if bookmark.missing {
+                    (
+                        debug_info::body(&bookmark.line),
+                        Style::default().fg(self.theme.hidden),
+                    )
                 } else {
-                    None
-                }
+                    let line = self.line(bookmark.index);
+                    match debug_info::line_kind(line) {
+                        LineKind::FuncEnter | LineKind::FuncExit => {
+                            let func_name = debug_info::func_name(line).unwrap_or_default();
+                            (func_name, self.theme.func_style(func_name))
+                        }
+                        _ => (
+                            debug_info::body(line),
+                            Style::default().fg(self.theme.value),
+                        ),
+                    }
+                };
+                Row::new(vec![
+                    Cell::from(if bookmark.missing {
+                        "-".to_string()
+                    } else {
+                        (bookmark.index + 1).to_string()
+                    })
+                    .style(Style::default().fg(self.theme.depth)),
+                    Cell::from(text).style(style),
+                    Cell::from(bookmark.note.as_ref())
+                        .style(Style::default().fg(self.theme.bookmark)),
+                ])
             })
             .collect::<Vec<_>>();
-        let widths = vec![Constraint::Length(chunk.width)];
-        let table = Table::new(rows).widths(&widths).highlight_symbol(" > ");
+        let widths = [
+            Constraint::Length(6),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ];
+        let table = Table::new(rows)
+            .header(Row::new(vec!["Line", "Text", "Note"]))
+            .widths(&widths)
+            .highlight_symbol(" > ");
         let mut table_state = TableState::default();
-        table_state.select(Some(self.idx_visibility));
+        table_state.select(Some(self.idx_bookmark));
         frame.render_stateful_widget(
-            table.block(Block::default().borders(Borders::ALL).title("Visibility")),
+            table.block(Block::default().borders(Borders::ALL).title("Bookmarks")),
             chunk,
             &mut table_state,
         );
     }
 
-    fn num_displayed_debug_info_lines(&self) -> usize {
-        let mut count = 0;
-        let mut display = true;
-        let mut depth = 0;
-        let mut display_depth = depth;
-        for line in self.debug_info.lines() {
-            if line.starts_with("[DEBUG:func_enter") {
-                let func_name = line.split_ascii_whitespace().last().unwrap();
-                if !self
-                    .visibility_hash_map
-                    .get(func_name)
-                    .map_or(false, |visibility| *visibility)
-                {
-                    display = false;
-                } else if display {
-                    display_depth = depth
-                }
-                if display {
-                    count += 1;
-                }
-                depth += 1;
-            } else if line.starts_with("[DEBUG:func_exit") {
-                depth -= 1;
-                if display_depth == depth {
-                    display = true;
-                }
-            } else if display && line.starts_with("[DEBUG:value") {
-                count += 1;
-            }
-        }
-        count
+    fn render_note_box<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        frame.render_widget(Clear, chunk);
+        frame.render_widget(
+            Paragraph::new(Spans::from(vec![
+                Span::raw(" "),
+                Span::raw(self.note_string.as_str()),
+                Span::styled(" ", Style::default().bg(self.theme.cursor)),
+            ]))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Note (line {})", self.note_index + 1)),
+            ),
+            chunk,
+        );
     }
 
-    fn num_displayed_visibility_entries(&self) -> usize {
-        self.visibility_info
+    fn render_stats<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        let columns = StatsColumn::ALL
+            .into_iter()
+            .filter(|column| {
+                self.stats_has_time
+                    || !matches!(column, StatsColumn::TotalTime | StatsColumn::SelfTime)
+            })
+            .collect::<Vec<_>>();
+        let header = Row::new(columns.iter().map(|column| {
+            if *column == self.stats_column {
+                Cell::from(format!(
+                    "{}{}",
+                    column.title(),
+                    if self.stats_reverse { "▲" } else { "▼" }
+                ))
+                .style(Style::default().add_modifier(Modifier::BOLD))
+            } else {
+                Cell::from(column.title())
+            }
+        }));
+        let rows = self
+            .stats
+            .iter()
+            .map(|func_stats| {
+                Row::new(columns.iter().map(|column| {
+                    match column {
+                        StatsColumn::FuncName => Cell::from(func_stats.func_name.as_ref())
+                            .style(self.theme.func_style(&func_stats.func_name)),
+                        StatsColumn::NumCalls => Cell::from(func_stats.num_calls.to_string()),
+                        StatsColumn::MaxDepth => Cell::from(func_stats.max_depth.to_string()),
+                        StatsColumn::Callers => Cell::from(func_stats.callers.len().to_string()),
+                        StatsColumn::Callees => Cell::from(func_stats.callees.len().to_string()),
+                        StatsColumn::TotalTime => Cell::from(
+                            func_stats
+                                .total_time
+                                .map_or(String::new(), |time| format!("{:.1?}", time)),
+                        ),
+                        StatsColumn::SelfTime => Cell::from(
+                            func_stats
+                                .self_time
+                                .map_or(String::new(), |time| format!("{:.1?}", time)),
+                        ),
+                    }
+                }))
+            })
+            .collect::<Vec<_>>();
+        let widths = columns
             .iter()
-            .filter(|entry| self.search_regex.is_match(&entry.func_name))
-            .count()
+            .map(|column| match column {
+                StatsColumn::FuncName => Constraint::Min(10),
+                StatsColumn::TotalTime | StatsColumn::SelfTime => Constraint::Length(9),
+                _ => Constraint::Length(7),
+            })
+            .collect::<Vec<_>>();
+        let table = Table::new(rows)
+            .header(header)
+            .widths(&widths)
+            .highlight_symbol(" > ");
+        let mut table_state = TableState::default();
+        table_state.select(Some(self.idx_stats));
+        frame.render_stateful_widget(
+            table.block(Block::default().borders(Borders::ALL).title("Stats")),
+            chunk,
+            &mut table_state,
+        );
     }
 
-    pub fn update_visibility_info_file(&mut self) -> Result<()> {
-        self.visibility_info
-            .write_toml_file(&self.visibility_info_file)
+    fn render_stats_detail<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        let field = |title: &'static str, value: String| {
+            Spans::from(vec![
+                Span::styled(title, Style::default().fg(self.theme.depth)),
+                Span::raw(value),
+            ])
+        };
+        let join = |names: &BTreeSet<String>| {
+            names
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let text = match self.stats.get(self.idx_stats) {
+            Some(func_stats) => {
+                let num_occurrences = func_stats.occurrences.len();
+                let idx_occurrence = match self.idx_occurrence {
+                    0 => 0,
+                    idx_occurrence => (idx_occurrence - 1) % num_occurrences + 1,
+                };
+                vec![
+                    field("Location: ", join(&func_stats.locations)),
+                    field("Threads: ", join(&func_stats.threads)),
+                    field("Callers: ", join(&func_stats.callers)),
+                    field("Callees: ", join(&func_stats.callees)),
+                    field(
+                        "Occurrence: ",
+                        format!("{}/{}", idx_occurrence, num_occurrences),
+                    ),
+                ]
+            }
+            None => Vec::new(),
+        };
+        frame.render_widget(
+            Paragraph::new(text)
+                .block(Block::default().borders(Borders::ALL))
+                .wrap(Wrap { trim: false }),
+            chunk,
+        );
     }
 
-    pub fn update_visibility(&mut self) {
-        if let Some(entry) = self
-            .visibility_info
-            .iter_mut()
-            .filter(|entry| self.search_regex.is_match(&entry.func_name))
-            .nth(self.idx_visibility)
-        {
-            entry.visibility ^= true;
-        }
-        self.visibility_hash_map = self.visibility_info.clone().into();
-        self.scroll = (0, 0);
+    fn render_popup<B: Backend>(&self, frame: &mut Frame<B>, chunk: Rect) {
+        frame.render_widget(Clear, chunk);
+        frame.render_widget(
+            Paragraph::new(self.popup_text.as_ref())
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(self.popup_title.as_ref()),
+                )
+                .wrap(Wrap { trim: false })
+                .scroll((self.popup_scroll, 0)),
+            chunk,
+        );
     }
 
-    pub fn mode_change(&mut self, mode: AppMode) {
-        self.mode = mode;
-        set_guidance(self.mode, &mut self.guidance);
+    fn render_conflict<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let lines = [
+            format!(
+                "{} has been modified by another process.",
+                self.visibility_info_file.display()
+            ),
+            String::new(),
+            format!(
+                "Merge [{}]  Overwrite [{}]  Cancel [{}]",
+                self.key_bindings
+                    .conflict
+                    .keys_string(ConflictAction::Merge),
+                self.key_bindings
+                    .conflict
+                    .keys_string(ConflictAction::Overwrite),
+                self.key_bindings
+                    .conflict
+                    .keys_string(ConflictAction::Cancel),
+            ),
+        ];
+        let width = std::cmp::min(
+            lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0) as u16
+                + 4,
+            area.width,
+        );
+        let height = std::cmp::min(lines.len() as u16 + 2, area.height);
+        let chunk = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+        frame.render_widget(Clear, chunk);
+        frame.render_widget(
+            Paragraph::new(
+                lines
+                    .into_iter()
+                    .map(|line| Spans::from(Span::raw(format!(" {}", line))))
+                    .collect::<Vec<_>>(),
+            )
+            .block(Block::default().borders(Borders::ALL).title("Save")),
+            chunk,
+        );
     }
 
-    pub fn update_search_regex(&mut self) {
+    fn render_help<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let help = handler::help(self.mode, &self.key_bindings);
+        let keys_width = help
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0) as u16;
+        let description_width = help
+            .iter()
+            .map(|(_, description)| description.chars().count())
+            .max()
+            .unwrap_or(0) as u16;
+        let width = std::cmp::min(keys_width + description_width + 7, area.width);
+        let height = std::cmp::min(help.len() as u16 + 2, area.height);
+        let chunk = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+        let rows = help
+            .iter()
+            .skip(self.help_scroll)
+            .map(|(keys, description)| {
+                Row::new(vec![
+                    Cell::from(keys.as_ref()).style(Style::default().fg(self.theme.value)),
+                    Cell::from(*description),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let widths = vec![
+            Constraint::Length(keys_width + 1),
+            Constraint::Length(description_width),
+        ];
+        frame.render_widget(Clear, chunk);
+        frame.render_widget(
+            Table::new(rows)
+                .widths(&widths)
+                .column_spacing(2)
+                .block(Block::default().borders(Borders::ALL).title("Help")),
+            chunk,
+        );
+    }
+
+    /// Updates the displayed lines, which must be done after debug_info, visibility or folds change.
+    fn update_displayed_lines(&mut self) {
+        self.displayed_lines = DisplayedLines::new(
+            &self.debug_info,
+            &self.visibility_hash_map,
+            &self.folded_calls,
+        );
+    }
+
+    /// Updates the offsets of lines, which must be done after debug_info changes.
+    fn update_line_offsets(&mut self) {
+        self.line_offsets = LineOffsets::new(&self.debug_info);
+    }
+
+    /// Returns the line of debug_info, which is empty if it does not exist.
+    fn line(&self, index: usize) -> &str {
+        self.line_offsets
+            .line(&self.debug_info, index)
+            .unwrap_or_default()
+    }
+
+    /// Returns the index of the displayed line at the row of the terminal.
+    fn displayed_line_at(&self, row: u16) -> Option<usize> {
+        // The breadcrumb is below the top border.
+        let top = self.chunk_debug_info.y + 2;
+        if row < top || self.chunk_debug_info.bottom() <= row + 1 {
+            return None;
+        }
+        let offset = (row - top) as usize;
+        if !self.wrap {
+            return Some(self.scroll.0 as usize + offset);
+        }
+        let inner_width = self.chunk_debug_info.width.saturating_sub(2).max(1) as usize;
+        let mut height = 0;
+        for (i_displayed, displayed_line) in self
+            .displayed_lines
+            .iter()
+            .enumerate()
+            .skip(self.scroll.0 as usize)
+        {
+            let line = self.line(displayed_line.index);
+            let width = 5
+                + 2 * displayed_line.depth
+                + debug_info::content(line, displayed_line.kind)
+                    .chars()
+                    .count()
+                + if displayed_line.folded {
+                    " {...}".len()
+                } else {
+                    0
+                }
+                + self
+                    .bookmarks
+                    .of_line(displayed_line.index)
+                    .filter(|bookmark| !bookmark.note.is_empty())
+                    .map_or(0, |bookmark| "  # ".len() + bookmark.note.chars().count());
+            height += width.div_ceil(inner_width);
+            if offset < height {
+                return Some(i_displayed);
+            }
+        }
+        None
+    }
+
+    fn num_displayed_debug_info_lines(&self) -> usize {
+        self.displayed_lines.len()
+    }
+
+    fn max_displayed_debug_info_width(&self) -> usize {
+        self.displayed_lines
+            .iter()
+            .map(|displayed_line| {
+                debug_info::content(self.line(displayed_line.index), displayed_line.kind)
+                    .chars()
+                    .count()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn num_displayed_visibility_entries(&self) -> usize {
+        self.visibility_rows().len()
+    }
+
+    /// Returns rows of the entries matching the search regex, which are grouped by their prefixes.
+    fn visibility_rows(&self) -> Vec<VisibilityRow> {
+        let idx_entries = self
+            .visibility_info
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.search_regex.is_match(&entry.func_name))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let mut visibility_rows = Vec::with_capacity(idx_entries.len());
+        self.push_visibility_rows(&idx_entries, 0, 0, &mut visibility_rows);
+        visibility_rows
+    }
+
+    /// Pushes rows of the sorted entries which share the prefix of the length.
+    ///
+    /// Entries sharing a longer prefix ending with `::` make a group if there are two or more,
+    /// where the prefix is extended as long as all of them share it.
+    fn push_visibility_rows(
+        &self,
+        idx_entries: &[usize],
+        parent_len: usize,
+        depth: usize,
+        visibility_rows: &mut Vec<VisibilityRow>,
+    ) {
+        let func_name = |idx: usize| self.visibility_info[idx].func_name.as_str();
+        let next_prefix = |func_name: &str, prefix_len: usize| {
+            func_name[prefix_len..]
+                .find("::")
+                .map(|pos| prefix_len + pos + 2)
+        };
+        let mut i = 0;
+        while i < idx_entries.len() {
+            if let Some(mut prefix_len) = next_prefix(func_name(idx_entries[i]), parent_len) {
+                let prefix = &func_name(idx_entries[i])[..prefix_len];
+                let len = idx_entries[i..]
+                    .iter()
+                    .take_while(|idx| func_name(**idx).starts_with(prefix))
+                    .count();
+                if len >= 2 {
+                    let group = &idx_entries[i..i + len];
+                    while let Some(longer_len) = next_prefix(func_name(group[0]), prefix_len) {
+                        let longer_prefix = &func_name(group[0])[..longer_len];
+                        if group
+                            .iter()
+                            .all(|idx| func_name(*idx).starts_with(longer_prefix))
+                        {
+                            prefix_len = longer_len;
+                        } else {
+                            break;
+                        }
+                    }
+                    visibility_rows.push(VisibilityRow::Group {
+                        prefix: func_name(group[0])[..prefix_len].to_string(),
+                        parent_len,
+                        depth,
+                        idx_entries: group.to_vec(),
+                    });
+                    self.push_visibility_rows(group, prefix_len, depth + 1, visibility_rows);
+                    i += len;
+                    continue;
+                }
+            }
+            visibility_rows.push(VisibilityRow::Entry {
+                idx: idx_entries[i],
+                parent_len,
+                depth,
+            });
+            i += 1;
+        }
+    }
+
+    pub fn is_on_debug_info(&self, column: u16, row: u16) -> bool {
+        is_on_chunk(self.chunk_debug_info, column, row)
+    }
+
+    pub fn is_on_visibility_info(&self, column: u16, row: u16) -> bool {
+        is_on_chunk(self.chunk_visibility_info, column, row)
+    }
+
+    pub fn is_on_rules(&self, column: u16, row: u16) -> bool {
+        is_on_chunk(self.chunk_rules, column, row)
+    }
+
+    pub fn is_on_stats(&self, column: u16, row: u16) -> bool {
+        is_on_chunk(self.chunk_stats, column, row)
+    }
+
+    pub fn is_on_bookmarks(&self, column: u16, row: u16) -> bool {
+        is_on_chunk(self.chunk_bookmarks, column, row)
+    }
+
+    pub fn is_on_diff(&self, column: u16, row: u16) -> bool {
+        is_on_chunk(self.chunk_diff, column, row)
+    }
+
+    pub fn update_visibility_info_file(&mut self) -> Result<()> {
+        self.visibility_info
+            .write_toml_file(&self.visibility_info_file)?;
+        self.saved_visibility_info = self.visibility_info.clone();
+        self.visibility_info_modified = visibility_info::modified_time(&self.visibility_info_file);
+        Ok(())
+    }
+
+    pub fn update_bookmarks_file(&mut self) -> Result<()> {
+        // Avoid creating an empty file next to the visibility_info file.
+        if self.bookmarks.is_empty() && !self.bookmarks_file.is_file() {
+            return Ok(());
+        }
+        self.bookmarks.write_toml_file(&self.bookmarks_file)
+    }
+
+    /// Saves visibility_info and bookmarks, and then quits.
+    pub fn quit(&mut self) {
+        self.quit_after_save = true;
+        self.save();
+    }
+
+    /// Saves visibility_info and bookmarks.
+    ///
+    /// If the visibility_info file has been modified by another process since it was read,
+    /// whether to merge the changes or overwrite them is asked.
+    /// Errors are shown in the status line, where the viewer is kept open.
+    pub fn save(&mut self) {
+        if visibility_info::modified_time(&self.visibility_info_file)
+            != self.visibility_info_modified
+        {
+            if self.mode != AppMode::ResolveConflict {
+                self.conflict_return_mode = self.mode;
+            }
+            self.mode_change(AppMode::ResolveConflict);
+            return;
+        }
+        let result = self.write_files();
+        self.report_save_error(result);
+    }
+
+    /// Saves after the visibility_info file is found to be modified by another process.
+    pub fn resolve_conflict(&mut self, action: ConflictAction) {
+        self.mode_change(self.conflict_return_mode);
+        let result = match action {
+            ConflictAction::Merge => self.merge_and_write_files(),
+            ConflictAction::Overwrite => self.write_files(),
+            ConflictAction::Cancel => {
+                self.quit_after_save = false;
+                Ok(())
+            }
+        };
+        self.report_save_error(result);
+    }
+
+    /// Merges changes in the visibility_info file into this, which is kept if anything fails.
+    fn merge_and_write_files(&mut self) -> Result<()> {
+        let theirs = VisibilityInfo::read_if_exists(&self.visibility_info_file)?;
+        self.visibility_info = self
+            .visibility_info
+            .clone()
+            .merge(self.saved_visibility_info.clone(), theirs)
+            .update_by_debug_info(&self.debug_info)?;
+        self.update_entry_occurrences(0);
+        self.idx_visibility_next(0);
+        self.idx_rule = std::cmp::min(
+            self.idx_rule,
+            self.visibility_info.rules.len().saturating_sub(1),
+        );
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.update_visibility_hash_map();
+        self.write_files()
+    }
+
+    fn write_files(&mut self) -> Result<()> {
+        self.update_visibility_info_file()?;
+        self.update_bookmarks_file()?;
+        self.status_message = "Saved".to_string();
+        if self.quit_after_save {
+            self.running = false;
+        }
+        Ok(())
+    }
+
+    /// Shows the error of saving, which cancels quitting so that edits are not lost.
+    fn report_save_error(&mut self, result: Result<()>) {
+        if let Err(err) = result {
+            self.status_message = format!("Error: {:#}", err);
+            self.quit_after_save = false;
+        }
+    }
+
+    /// Toggles the selected entry, or all the entries in the selected group,
+    /// which are hidden if all of them are shown and shown otherwise.
+    pub fn update_visibility(&mut self) {
+        match self.visibility_rows().get(self.idx_visibility).cloned() {
+            Some(VisibilityRow::Group { idx_entries, .. }) => {
+                let visibility = if idx_entries.iter().all(|idx| {
+                    self.visibility_info
+                        .visibility_of_entry(&self.visibility_info[*idx])
+                        .shows_frame()
+                }) {
+                    Visibility::HideSubtree
+                } else {
+                    Visibility::Show
+                };
+                self.edit_entries(idx_entries, |_| Some(visibility));
+            }
+            Some(VisibilityRow::Entry { idx, .. }) => {
+                self.edit_entries(vec![idx], |visibility| Some(visibility.toggle()))
+            }
+            None => (),
+        }
+    }
+
+    /// Switches the selected entry to the next visibility mode.
+    pub fn cycle_visibility_mode(&mut self) {
+        self.edit_selected_entry(|visibility| Some(visibility.next()));
+    }
+
+    /// Leaves the visibility of the selected entry to rules.
+    pub fn unset_visibility(&mut self) {
+        self.edit_selected_entry(|_| None);
+    }
+
+    /// Adds an entry for each location of the functions of the selected row
+    /// so that the locations can have different visibility.
+    pub fn add_location_entries(&mut self) {
+        let func_names =
+            self.visibility_rows()
+                .get(self.idx_visibility)
+                .map_or(Vec::new(), |visibility_row| {
+                    visibility_row
+                        .idx_entries()
+                        .into_iter()
+                        .map(|idx| self.visibility_info[idx].func_name.clone())
+                        .collect()
+                });
+        let mut added = false;
+        for func_name in func_names {
+            if let Some(locations) = self.call_tree.func_locations.get(&func_name) {
+                added |= self.visibility_info.add_locations(
+                    &func_name,
+                    &locations.iter().map(String::as_str).collect::<Vec<_>>(),
+                );
+            }
+        }
+        if added {
+            self.update_entry_occurrences(0);
+            self.update_visibility_hash_map();
+        }
+    }
+
+    /// Shows, hides or inverts all the entries matching the search regex.
+    pub fn update_visibility_of_matches(&mut self, action: VisibilityAction) {
+        let idx_entries = self
+            .visibility_info
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.search_regex.is_match(&entry.func_name))
+            .map(|(idx, _)| idx)
+            .collect();
+        match action {
+            VisibilityAction::ShowAll => self.edit_entries(idx_entries, |_| Some(Visibility::Show)),
+            VisibilityAction::HideAll => {
+                self.edit_entries(idx_entries, |_| Some(Visibility::HideSubtree))
+            }
+            VisibilityAction::Invert => {
+                self.edit_entries(idx_entries, |visibility| Some(visibility.toggle()))
+            }
+            _ => (),
+        }
+    }
+
+    /// Changes the selected entry or all the entries in the selected group.
+    fn edit_selected_entry(&mut self, visibility: impl Fn(Visibility) -> Option<Visibility>) {
+        if let Some(visibility_row) = self.visibility_rows().get(self.idx_visibility) {
+            self.edit_entries(visibility_row.idx_entries(), visibility);
+        }
+    }
+
+    /// Adds the calls from `first_call` to the occurrences of their entries,
+    /// where all the occurrences are collected again if it is zero.
+    ///
+    /// This must be done after entries are added or removed and after calls are added.
+    fn update_entry_occurrences(&mut self, first_call: usize) {
+        if first_call == 0 {
+            self.entry_occurrences = vec![Vec::new(); self.visibility_info.len()];
+        }
+        for call in self.call_tree.calls.iter().skip(first_call) {
+            // An entry without a location counts the calls at all the locations.
+            for location in [None, Some(call.location.as_str())] {
+                if let Ok(idx) = self.visibility_info.position(&call.func_name, location) {
+                    self.entry_occurrences[idx].push(call.enter_index);
+                }
+            }
+        }
+    }
+
+    /// Returns the `func_enter` lines of the functions of the entries in order.
+    fn occurrences(&self, idx_entries: &[usize]) -> Vec<usize> {
+        let mut occurrences = idx_entries
+            .iter()
+            .flat_map(|idx| self.entry_occurrences[*idx].iter().copied())
+            .collect::<Vec<_>>();
+        occurrences.sort_unstable();
+        occurrences
+    }
+
+    /// Scrolls the DebugInfo pane to the first occurrence of the selected functions,
+    /// or to the next one if it is repeated.
+    pub fn jump_to_visibility_occurrence(&mut self) {
+        let occurrences = match self.visibility_rows().get(self.idx_visibility) {
+            Some(visibility_row) => self.occurrences(&visibility_row.idx_entries()),
+            None => return,
+        };
+        if occurrences.is_empty() {
+            return;
+        }
+        let index = occurrences[self.idx_visibility_occurrence % occurrences.len()];
+        self.idx_visibility_occurrence += 1;
+        self.scroll_to_line(index);
+    }
+
+    /// Changes the visibility of the entries from the current one, which can be undone.
+    fn edit_entries(
+        &mut self,
+        idx_entries: Vec<usize>,
+        visibility: impl Fn(Visibility) -> Option<Visibility>,
+    ) {
+        let changes = idx_entries
+            .into_iter()
+            .filter_map(|idx| {
+                let entry = &self.visibility_info[idx];
+                let new_visibility = visibility(self.visibility_info.visibility_of_entry(entry));
+                (new_visibility != entry.visibility).then(|| EntryEdit {
+                    func_name: entry.func_name.clone(),
+                    location: entry.location.clone(),
+                    old_visibility: entry.visibility,
+                    new_visibility,
+                })
+            })
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            let edit = VisibilityEdit::Entries(changes);
+            self.apply_edit(&edit, false);
+            self.push_edit(edit);
+        }
+    }
+
+    /// Records the edit which has been applied.
+    fn push_edit(&mut self, edit: VisibilityEdit) {
+        self.undo_stack.push(edit);
+        self.redo_stack.clear();
+    }
+
+    /// Applies the edit, or reverts it if `undo` is true.
+    fn apply_edit(&mut self, edit: &VisibilityEdit, undo: bool) {
+        match edit {
+            VisibilityEdit::Entries(changes) => {
+                for change in changes {
+                    if let Ok(idx) = self
+                        .visibility_info
+                        .position(&change.func_name, change.location.as_deref())
+                    {
+                        self.visibility_info[idx].visibility = if undo {
+                            change.old_visibility
+                        } else {
+                            change.new_visibility
+                        };
+                    }
+                }
+            }
+            VisibilityEdit::Rules(old_rules, new_rules) => {
+                self.visibility_info.rules = if undo {
+                    old_rules.clone()
+                } else {
+                    new_rules.clone()
+                };
+                self.idx_rule = std::cmp::min(
+                    self.idx_rule,
+                    self.visibility_info.rules.len().saturating_sub(1),
+                );
+            }
+            VisibilityEdit::Values(old_values, new_values) => {
+                self.visibility_info.values = if undo {
+                    old_values.clone()
+                } else {
+                    new_values.clone()
+                };
+            }
+        }
+        self.update_visibility_hash_map();
+    }
+
+    /// Reverts the last edit of visibility information.
+    pub fn undo_visibility(&mut self) {
+        if let Some(edit) = self.undo_stack.pop() {
+            self.apply_edit(&edit, true);
+            self.redo_stack.push(edit);
+        }
+    }
+
+    /// Applies the last reverted edit of visibility information again.
+    pub fn redo_visibility(&mut self) {
+        if let Some(edit) = self.redo_stack.pop() {
+            self.apply_edit(&edit, false);
+            self.undo_stack.push(edit);
+        }
+    }
+
+    /// Switches to the next profile of visibility information.
+    pub fn next_profile(&mut self) -> Result<()> {
+        let profiles = self.visibility_info.profiles();
+        let idx = profiles
+            .iter()
+            .position(|profile| *profile == self.visibility_info.profile())
+            .unwrap_or_default();
+        let profile = profiles[(idx + 1) % profiles.len()].map(str::to_string);
+        self.visibility_info.select_profile(profile.as_deref());
+        self.visibility_info =
+            std::mem::take(&mut self.visibility_info).update_by_debug_info(&self.debug_info)?;
+        self.update_entry_occurrences(0);
+        self.idx_visibility = 0;
+        self.idx_rule = 0;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.update_visibility_hash_map();
+        Ok(())
+    }
+
+    /// Removes entries of functions which are not found in debug_info.
+    pub fn prune_visibility_info(&mut self) {
+        self.visibility_info.prune(None);
+        self.update_entry_occurrences(0);
+        self.idx_visibility_next(0);
+        self.update_visibility_hash_map();
+    }
+
+    /// Applies visibility_info to the DebugInfo pane, keeping the line at the top as far as possible.
+    fn update_visibility_hash_map(&mut self) {
+        let index = self
+            .displayed_lines
+            .get(self.scroll.0 as usize)
+            .map(|displayed_line| displayed_line.index);
+        self.visibility_hash_map = self.visibility_info.clone().into();
+        self.update_displayed_lines();
+        match index {
+            Some(index) => self.scroll_to_line(index),
+            None => self.scroll.0 = 0,
+        }
+    }
+
+    /// Adds a rule which hides functions matching the search regex.
+    pub fn add_rule_from_search(&mut self) {
+        if self.search_string.is_empty() || !self.search_regex_error.is_empty() {
+            return;
+        }
+        if let Ok(rule) = VisibilityRule::try_new(
+            self.search_string.clone(),
+            PatternSyntax::Regex,
+            Visibility::HideSubtree,
+        ) {
+            let mut rules = self.visibility_info.rules.clone();
+            rules.push(rule);
+            self.idx_rule = rules.len() - 1;
+            let edit = VisibilityEdit::Rules(self.visibility_info.rules.clone(), rules);
+            self.apply_edit(&edit, false);
+            self.push_edit(edit);
+        }
+    }
+
+    /// Changes the value filter, which can be undone.
+    fn edit_values(&mut self, values: ValueFilter) {
+        if values == self.visibility_info.values {
+            return;
+        }
+        let edit = VisibilityEdit::Values(self.visibility_info.values.clone(), values);
+        self.apply_edit(&edit, false);
+        self.push_edit(edit);
+    }
+
+    /// Shows or hides all the value lines.
+    pub fn toggle_values(&mut self) {
+        let mut values = self.visibility_info.values.clone();
+        values.hide_all ^= true;
+        self.edit_values(values);
+    }
+
+    /// Hides the value lines of the expression of the value line at the top.
+    pub fn hide_expr_at_top(&mut self) {
+        let displayed_line = match self.displayed_lines.get(self.scroll.0 as usize) {
+            Some(displayed_line) if displayed_line.kind == LineKind::Value => *displayed_line,
+            _ => return,
+        };
+        let line = self.line(displayed_line.index);
+        let (expr, _) = debug_info::split_value(debug_info::body(line));
+        let mut values = self.visibility_info.values.clone();
+        values.hidden_exprs.push(expr.to_string());
+        self.edit_values(values);
+    }
+
+    /// Shows the value lines of all the hidden expressions again.
+    pub fn show_hidden_exprs(&mut self) {
+        let mut values = self.visibility_info.values.clone();
+        values.hidden_exprs.clear();
+        self.edit_values(values);
+    }
+
+    /// Shows only the value lines matching the search regex, or all of them if it is empty.
+    pub fn filter_values_by_search(&mut self) {
+        if !self.search_regex_error.is_empty() {
+            return;
+        }
+        let mut values = self.visibility_info.values.clone();
+        let pattern = (!self.search_string.is_empty()).then(|| self.search_string.clone());
+        if values.set_pattern(pattern).is_ok() {
+            self.edit_values(values);
+        }
+    }
+
+    pub fn update_rule(&mut self, action: RulesAction) {
+        let idx_rule = self.idx_rule;
+        let old_rules = self.visibility_info.rules.clone();
+        let rules = &mut self.visibility_info.rules;
+        if idx_rule >= rules.len() {
+            return;
+        }
+        self.rules_error.clear();
+        match action {
+            RulesAction::Toggle => rules[idx_rule].visibility = rules[idx_rule].visibility.toggle(),
+            RulesAction::Mode => rules[idx_rule].visibility = rules[idx_rule].visibility.next(),
+            RulesAction::Syntax => {
+                if let Err(err) = rules[idx_rule].toggle_syntax() {
+                    self.rules_error = err.root_cause().to_string();
+                }
+            }
+            RulesAction::Delete => {
+                rules.remove(idx_rule);
+                self.idx_rule = std::cmp::min(idx_rule, rules.len().saturating_sub(1));
+            }
+            RulesAction::Raise if idx_rule > 0 => {
+                rules.swap(idx_rule - 1, idx_rule);
+                self.idx_rule -= 1;
+            }
+            RulesAction::Lower if idx_rule + 1 < rules.len() => {
+                rules.swap(idx_rule, idx_rule + 1);
+                self.idx_rule += 1;
+            }
+            _ => return,
+        }
+        if self.rules_error.is_empty() {
+            let new_rules = self.visibility_info.rules.clone();
+            self.push_edit(VisibilityEdit::Rules(old_rules, new_rules));
+        }
+        self.update_visibility_hash_map();
+    }
+
+    pub fn idx_rule_prev(&mut self, n: usize) {
+        self.idx_rule = self.idx_rule.saturating_sub(n);
+    }
+
+    pub fn idx_rule_next(&mut self, n: usize) {
+        self.idx_rule = std::cmp::min(
+            self.idx_rule.saturating_add(n),
+            self.visibility_info.rules.len().saturating_sub(1),
+        );
+    }
+
+    /// Selects the clicked rule, or toggles it if it has been already selected.
+    pub fn click_rules(&mut self, row: u16) {
+        // The header is below the top border.
+        let top = self.chunk_rules.y + 2;
+        if row < top || self.chunk_rules.bottom() <= row + 1 {
+            return;
+        }
+        let num_rows = self.chunk_rules.height.saturating_sub(3) as usize;
+        let offset = (self.idx_rule + 1).saturating_sub(num_rows);
+        let idx_rule = offset + (row - top) as usize;
+        if idx_rule == self.idx_rule {
+            self.update_rule(RulesAction::Toggle);
+        } else if idx_rule < self.visibility_info.rules.len() {
+            self.idx_rule = idx_rule;
+        }
+    }
+
+    pub fn mode_change(&mut self, mode: AppMode) {
+        self.mode = mode;
+    }
+
+    pub fn update_search_regex(&mut self) {
         self.search_regex_error.clear();
         match Regex::new(&self.search_string) {
             Ok(regex) => self.search_regex = regex,
@@ -377,12 +2113,412 @@ impl App {
 
     pub fn scroll_down(&mut self, n: u16) {
         let num_displayed_debug_info_lines = self.num_displayed_debug_info_lines();
-        if ((self.scroll.0 + n) as usize) < num_displayed_debug_info_lines - 1 {
+        if (self.scroll.0.saturating_add(n) as usize)
+            < num_displayed_debug_info_lines.saturating_sub(1)
+        {
             self.scroll.0 += n;
         } else {
-            self.scroll.0 =
-                std::cmp::min(num_displayed_debug_info_lines - 1, u16::MAX as usize) as u16;
+            self.scroll.0 = std::cmp::min(
+                num_displayed_debug_info_lines.saturating_sub(1),
+                u16::MAX as usize,
+            ) as u16;
+        }
+    }
+
+    /// Scrolls the DebugInfo pane so that the last line is at the bottom.
+    pub fn scroll_to_last_page(&mut self) {
+        // The borders and the breadcrumb are not lines of debug_info.
+        let num_rows = self.chunk_debug_info.height.saturating_sub(3) as usize;
+        self.scroll.0 = std::cmp::min(
+            self.num_displayed_debug_info_lines()
+                .saturating_sub(num_rows),
+            u16::MAX as usize,
+        ) as u16;
+    }
+
+    pub fn scroll_left(&mut self, n: u16) {
+        self.scroll.1 = self.scroll.1.saturating_sub(n);
+    }
+
+    pub fn scroll_right(&mut self, n: u16) {
+        let max_width = self.max_displayed_debug_info_width();
+        self.scroll.1 = std::cmp::min(
+            self.scroll.1.saturating_add(n) as usize,
+            max_width.saturating_sub(1),
+        ) as u16;
+    }
+
+    /// Folds or unfolds the call at the `i_displayed`-th displayed line.
+    pub fn toggle_fold(&mut self, i_displayed: usize) {
+        if let Some(displayed_line) = self.displayed_lines.get(i_displayed) {
+            if displayed_line.kind == LineKind::FuncEnter {
+                if !self.folded_calls.remove(&displayed_line.index) {
+                    self.folded_calls.insert(displayed_line.index);
+                }
+                self.update_displayed_lines();
+            }
         }
+        self.scroll_down(0);
+    }
+
+    pub fn toggle_fold_at_top(&mut self) {
+        self.toggle_fold(self.scroll.0 as usize);
+    }
+
+    pub fn click_debug_info(&mut self, row: u16) {
+        if let Some(i_displayed) = self.displayed_line_at(row) {
+            self.toggle_fold(i_displayed);
+        }
+    }
+
+    /// Selects the clicked entry, or toggles it if it has been already selected.
+    pub fn click_visibility_info(&mut self, row: u16) {
+        // The first row is the header.
+        let top = self.chunk_visibility_info.y + 2;
+        if row < top || self.chunk_visibility_info.bottom() <= row + 1 {
+            return;
+        }
+        let num_rows = self.chunk_visibility_info.height.saturating_sub(3) as usize;
+        let offset = (self.idx_visibility + 1).saturating_sub(num_rows);
+        let idx_visibility = offset + (row - top) as usize;
+        if idx_visibility == self.idx_visibility {
+            self.update_visibility();
+        } else if idx_visibility < self.num_displayed_visibility_entries() {
+            self.idx_visibility = idx_visibility;
+            self.idx_visibility_occurrence = 0;
+        }
+    }
+
+    /// Scrolls the DebugInfo pane so that the line or the nearest displayed line before it is at the top.
+    pub fn scroll_to_line(&mut self, index: usize) {
+        let i_displayed = self
+            .displayed_lines
+            .partition_point(|displayed_line| displayed_line.index <= index)
+            .saturating_sub(1);
+        self.scroll.0 = std::cmp::min(i_displayed, u16::MAX as usize) as u16;
+    }
+
+    pub fn open_stats(&mut self) {
+        self.update_stats();
+        self.mode_change(AppMode::ViewStats);
+    }
+
+    pub fn update_stats(&mut self) {
+        self.stats_has_time = self.call_tree.has_time();
+        if !self.stats_has_time
+            && matches!(
+                self.stats_column,
+                StatsColumn::TotalTime | StatsColumn::SelfTime
+            )
+        {
+            self.stats_column = StatsColumn::NumCalls;
+        }
+        self.stats = stats::func_stats(
+            &self.call_tree,
+            self.visibility_info
+                .iter()
+                .map(|entry| entry.func_name.as_str()),
+        );
+        stats::sort(&mut self.stats, self.stats_column, self.stats_reverse);
+        self.idx_stats = std::cmp::min(self.idx_stats, self.stats.len().saturating_sub(1));
+        self.idx_occurrence = 0;
+    }
+
+    pub fn sort_stats(&mut self, action: StatsAction) {
+        let func_name = self
+            .stats
+            .get(self.idx_stats)
+            .map(|func_stats| func_stats.func_name.clone());
+        match action {
+            StatsAction::SortColumn => {
+                self.stats_column = self.stats_column.next(self.stats_has_time);
+                self.stats_reverse = false;
+            }
+            StatsAction::ReverseSort => self.stats_reverse ^= true,
+            _ => (),
+        }
+        stats::sort(&mut self.stats, self.stats_column, self.stats_reverse);
+        if let Some(func_name) = func_name {
+            self.idx_stats = self
+                .stats
+                .iter()
+                .position(|func_stats| func_stats.func_name == func_name)
+                .unwrap_or(0);
+        }
+    }
+
+    /// Scrolls the DebugInfo pane to the next occurrence of the selected function.
+    pub fn jump_to_occurrence(&mut self) {
+        let index = match self.stats.get(self.idx_stats) {
+            Some(func_stats) if !func_stats.occurrences.is_empty() => {
+                func_stats.occurrences[self.idx_occurrence % func_stats.occurrences.len()]
+            }
+            _ => return,
+        };
+        self.idx_occurrence += 1;
+        self.scroll_to_line(index);
+    }
+
+    pub fn idx_stats_prev(&mut self, n: usize) {
+        self.idx_stats = self.idx_stats.saturating_sub(n);
+        self.idx_occurrence = 0;
+    }
+
+    pub fn idx_stats_next(&mut self, n: usize) {
+        self.idx_stats = std::cmp::min(
+            self.idx_stats.saturating_add(n),
+            self.stats.len().saturating_sub(1),
+        );
+        self.idx_occurrence = 0;
+    }
+
+    /// Selects the clicked function, or jumps to its occurrence if it has been already selected.
+    pub fn click_stats(&mut self, row: u16) {
+        // The header is below the top border.
+        let top = self.chunk_stats.y + 2;
+        if row < top || self.chunk_stats.bottom() <= row + 1 {
+            return;
+        }
+        let num_rows = self.chunk_stats.height.saturating_sub(3) as usize;
+        let offset = (self.idx_stats + 1).saturating_sub(num_rows);
+        let idx_stats = offset + (row - top) as usize;
+        if idx_stats == self.idx_stats {
+            self.jump_to_occurrence();
+        } else if idx_stats < self.stats.len() {
+            self.idx_stats = idx_stats;
+            self.idx_occurrence = 0;
+        }
+    }
+
+    /// Bookmarks the line at the top of the DebugInfo pane, or unbookmarks it.
+    pub fn toggle_bookmark_at_top(&mut self) {
+        if let Some(displayed_line) = self.displayed_lines.get(self.scroll.0 as usize) {
+            if let Some(line) = self
+                .line_offsets
+                .line(&self.debug_info, displayed_line.index)
+            {
+                self.bookmarks.toggle(displayed_line.index, line);
+            }
+        }
+    }
+
+    pub fn annotate_at_top(&mut self) {
+        if let Some(displayed_line) = self.displayed_lines.get(self.scroll.0 as usize) {
+            self.open_note_editor(displayed_line.index);
+        }
+    }
+
+    pub fn annotate_bookmark(&mut self) {
+        match self.bookmarks.get(self.idx_bookmark) {
+            Some(bookmark) if !bookmark.missing => self.open_note_editor(bookmark.index),
+            _ => (),
+        }
+    }
+
+    fn open_note_editor(&mut self, index: usize) {
+        self.note_index = index;
+        self.note_string = self
+            .bookmarks
+            .of_line(index)
+            .map_or(String::new(), |bookmark| bookmark.note.clone());
+        self.note_return_mode = self.mode;
+        self.mode_change(AppMode::EditNote);
+    }
+
+    /// Saves the note, adding a bookmark if the line has not been bookmarked.
+    pub fn finish_note(&mut self) {
+        let line = self
+            .line_offsets
+            .line(&self.debug_info, self.note_index)
+            .unwrap_or_default();
+        self.bookmarks
+            .annotate(self.note_index, line, std::mem::take(&mut self.note_string));
+        self.mode_change(self.note_return_mode);
+    }
+
+    /// Closes the note editor without changing the note.
+    pub fn cancel_note(&mut self) {
+        self.note_string.clear();
+        self.mode_change(self.note_return_mode);
+    }
+
+    pub fn delete_bookmark(&mut self) {
+        if self.idx_bookmark < self.bookmarks.len() {
+            self.bookmarks.remove(self.idx_bookmark);
+            self.idx_bookmark =
+                std::cmp::min(self.idx_bookmark, self.bookmarks.len().saturating_sub(1));
+        }
+    }
+
+    pub fn jump_to_bookmark(&mut self) {
+        match self.bookmarks.get(self.idx_bookmark) {
+            Some(bookmark) if !bookmark.missing => self.scroll_to_line(bookmark.index),
+            _ => (),
+        }
+    }
+
+    pub fn idx_bookmark_prev(&mut self, n: usize) {
+        self.idx_bookmark = self.idx_bookmark.saturating_sub(n);
+    }
+
+    pub fn idx_bookmark_next(&mut self, n: usize) {
+        self.idx_bookmark = std::cmp::min(
+            self.idx_bookmark.saturating_add(n),
+            self.bookmarks.len().saturating_sub(1),
+        );
+    }
+
+    /// Selects the clicked bookmark, or jumps to its line if it has been already selected.
+    pub fn click_bookmarks(&mut self, row: u16) {
+        // The header is below the top border.
+        let top = self.chunk_bookmarks.y + 2;
+        if row < top || self.chunk_bookmarks.bottom() <= row + 1 {
+            return;
+        }
+        let num_rows = self.chunk_bookmarks.height.saturating_sub(3) as usize;
+        let offset = (self.idx_bookmark + 1).saturating_sub(num_rows);
+        let idx_bookmark = offset + (row - top) as usize;
+        if idx_bookmark == self.idx_bookmark {
+            self.jump_to_bookmark();
+        } else if idx_bookmark < self.bookmarks.len() {
+            self.idx_bookmark = idx_bookmark;
+        }
+    }
+
+    /// Sets debug information compared in the diff mode.
+    pub fn set_diff_file(&mut self, diff_file: PathBuf) -> Result<()> {
+        self.diff_file = Some(diff_file);
+        self.read_diff_debug_info()
+    }
+
+    fn read_diff_debug_info(&mut self) -> Result<()> {
+        if let Some(diff_file) = &self.diff_file {
+            self.diff_debug_info = fs::read_to_string(diff_file)
+                .with_context(|| format!("failed to read {}", diff_file.display()))?;
+            self.diff_call_tree = CallTree::new(&self.diff_debug_info);
+            if self.mode == AppMode::ViewDiff {
+                self.update_diff();
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the diff at the first divergence, which does nothing if `--diff` is not given.
+    pub fn open_diff(&mut self) {
+        if self.diff_file.is_none() {
+            return;
+        }
+        self.update_diff();
+        self.diff_scroll = self.trace_diff.first_divergence.unwrap_or(0);
+        self.mode_change(AppMode::ViewDiff);
+    }
+
+    /// Aligns the traces again, skipping hidden functions.
+    pub fn update_diff(&mut self) {
+        // Rules are also applied to functions which are only in the other trace.
+        let mut visibility_hash_map = self.visibility_hash_map.clone();
+        for call in self.diff_call_tree.calls.iter() {
+            if !visibility_hash_map.contains_function(&call.func_name) {
+                visibility_hash_map.insert_function(
+                    call.func_name.clone(),
+                    self.visibility_info.visibility(&call.func_name),
+                );
+            }
+        }
+        self.trace_diff = TraceDiff::new(
+            &self.debug_info,
+            &self.call_tree,
+            &self.diff_debug_info,
+            &self.diff_call_tree,
+            Some(&visibility_hash_map),
+        );
+        self.diff_scroll = std::cmp::min(
+            self.diff_scroll,
+            self.trace_diff.rows.len().saturating_sub(1),
+        );
+    }
+
+    pub fn diff_scroll_up(&mut self, n: usize) {
+        self.diff_scroll = self.diff_scroll.saturating_sub(n);
+    }
+
+    pub fn diff_scroll_down(&mut self, n: usize) {
+        self.diff_scroll = std::cmp::min(
+            self.diff_scroll.saturating_add(n),
+            self.trace_diff.rows.len().saturating_sub(1),
+        );
+    }
+
+    pub fn move_to_difference(&mut self, action: DiffAction) {
+        let i_row = match action {
+            DiffAction::NextDifference => self.trace_diff.next_difference(self.diff_scroll),
+            DiffAction::PrevDifference => self.trace_diff.prev_difference(self.diff_scroll),
+            _ => None,
+        };
+        if let Some(i_row) = i_row {
+            self.diff_scroll = i_row;
+        }
+    }
+
+    /// Closes the diff and scrolls the DebugInfo pane to the line of the first trace at the top row.
+    pub fn jump_to_diff_row(&mut self) {
+        if let (Some(index), _) = self.trace_diff.lines_from(self.diff_scroll) {
+            self.scroll_to_line(index);
+        }
+        self.mode_change(AppMode::ViewDebug);
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.wrap ^= true;
+    }
+
+    /// Opens the value line at the top of the DebugInfo pane in a popup.
+    pub fn open_value_popup(&mut self) {
+        let displayed_line = match self.displayed_lines.get(self.scroll.0 as usize) {
+            Some(displayed_line) if displayed_line.kind == LineKind::Value => *displayed_line,
+            _ => return,
+        };
+        let line = match self
+            .line_offsets
+            .line(&self.debug_info, displayed_line.index)
+        {
+            Some(line) => line,
+            None => return,
+        };
+        let (expr, value) = debug_info::split_value(debug_info::body(line));
+        self.popup_title = format!("Value ({})", debug_info::location(line).unwrap_or_default());
+        self.popup_text = format!("{} = {}", expr, debug_info::pretty_debug(value));
+        self.popup_scroll = 0;
+        self.mode_change(AppMode::ViewValue);
+    }
+
+    pub fn open_help(&mut self) {
+        self.show_help = true;
+        self.help_scroll = 0;
+    }
+
+    pub fn help_scroll_up(&mut self, n: usize) {
+        self.help_scroll = self.help_scroll.saturating_sub(n);
+    }
+
+    pub fn help_scroll_down(&mut self, n: usize) {
+        let num_help_lines = handler::help(self.mode, &self.key_bindings).len();
+        self.help_scroll = std::cmp::min(
+            self.help_scroll.saturating_add(n),
+            num_help_lines.saturating_sub(1),
+        );
+    }
+
+    pub fn popup_scroll_up(&mut self, n: u16) {
+        self.popup_scroll = self.popup_scroll.saturating_sub(n);
+    }
+
+    pub fn popup_scroll_down(&mut self, n: u16) {
+        let num_popup_lines = self.popup_text.lines().count();
+        self.popup_scroll = std::cmp::min(
+            self.popup_scroll.saturating_add(n) as usize,
+            num_popup_lines.saturating_sub(1),
+        ) as u16;
     }
 
     pub fn idx_visibility_prev(&mut self, n: usize) {
@@ -391,14 +2527,119 @@ impl App {
         } else {
             self.idx_visibility -= n;
         }
+        self.idx_visibility_occurrence = 0;
     }
 
     pub fn idx_visibility_next(&mut self, n: usize) {
         let num_displayed_visibility_entries = self.num_displayed_visibility_entries();
-        if self.idx_visibility + n < num_displayed_visibility_entries - 1 {
+        if self.idx_visibility.saturating_add(n)
+            < num_displayed_visibility_entries.saturating_sub(1)
+        {
             self.idx_visibility += n;
         } else {
-            self.idx_visibility = num_displayed_visibility_entries - 1;
+            self.idx_visibility = num_displayed_visibility_entries.saturating_sub(1);
         }
+        self.idx_visibility_occurrence = 0;
+    }
+}
+
+fn is_on_chunk(chunk: Rect, column: u16, row: u16) -> bool {
+    chunk.x <= column && column < chunk.right() && chunk.y <= row && row < chunk.bottom()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DEBUG_INFO: &str = "\
+[DEBUG:func_enter(src/main.rs:1)] main
+[DEBUG:func_enter(src/lib.rs:5)] foo
+[DEBUG:func_exit(src/lib.rs:5)] foo
+";
+
+    /// Returns an app following the file into which the text is written.
+    fn follow(name: &str, debug_info: &str) -> App {
+        let dir =
+            std::env::temp_dir().join(format!("proctrack-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let debug_info_file = dir.join(name);
+        fs::write(&debug_info_file, debug_info).unwrap();
+        let visibility_info = VisibilityInfo::default()
+            .update_by_debug_info(debug_info)
+            .unwrap();
+        let mut app = App::new(
+            debug_info_file,
+            dir.join(format!("{}.toml", name)),
+            debug_info.to_string(),
+            visibility_info,
+            VisibilityInfo::default(),
+            None,
+            Bookmarks::default(),
+            KeyBindings::preset("default").unwrap(),
+            Theme::default(),
+        );
+        app.start_following().unwrap();
+        app
+    }
+
+    fn append(app: &App, text: &str) {
+        File::options()
+            .append(true)
+            .open(&app.debug_info_file)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .unwrap();
+    }
+
+    #[test]
+    fn follow_lines_appended_in_pieces() {
+        let mut app = follow(
+            "pieces.txt",
+            "[DEBUG:func_enter(src/main.rs:1)] main\n[DEBUG:func_en",
+        );
+        assert_eq!(app.debug_info, "[DEBUG:func_enter(src/main.rs:1)] main\n");
+        append(
+            &app,
+            "ter(src/lib.rs:5)] foo\n[DEBUG:func_exit(src/lib.rs:5)] f",
+        );
+        app.read_appended_debug_info().unwrap();
+        assert_eq!(
+            app.debug_info,
+            &DEBUG_INFO[..DEBUG_INFO.rfind("[DEBUG:func_exit").unwrap()]
+        );
+        assert_eq!(app.line(1), "[DEBUG:func_enter(src/lib.rs:5)] foo");
+        assert_eq!(app.call_tree.calls[1].exit_index, None);
+        append(&app, "oo\n");
+        app.read_appended_debug_info().unwrap();
+        assert_eq!(app.debug_info, DEBUG_INFO);
+        assert_eq!(app.call_tree.calls[1].exit_index, Some(2));
+        assert_eq!(app.call_tree.calls[1].parent, Some(0));
+        assert_eq!(app.line(2), "[DEBUG:func_exit(src/lib.rs:5)] foo");
+        assert_eq!(app.line(3), "");
+        assert_eq!(
+            *app.displayed_lines,
+            *DisplayedLines::new(DEBUG_INFO, &app.visibility_hash_map, &HashSet::new())
+        );
+        fs::remove_dir_all(app.debug_info_file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn follow_rewritten_file_keeping_visibility_edits() {
+        let mut app = follow("rewritten.txt", DEBUG_INFO);
+        let idx = app.visibility_info.position("foo", None).unwrap();
+        app.edit_entries(vec![idx], |_| Some(Visibility::HideSubtree));
+        let rewritten = "[DEBUG:func_enter(src/lib.rs:5)] foo\n";
+        fs::write(&app.debug_info_file, rewritten).unwrap();
+        app.read_appended_debug_info().unwrap();
+        assert_eq!(app.debug_info, rewritten);
+        assert_eq!(app.line_offsets.line(&app.debug_info, 1), None);
+        assert_eq!(app.call_tree.calls.len(), 1);
+        assert_eq!(app.call_tree.calls[0].enter_index, 0);
+        assert!(app.displayed_lines.is_empty());
+        assert_eq!(app.undo_stack.len(), 1);
+        let entry = &app.visibility_info[app.visibility_info.position("foo", None).unwrap()];
+        assert_eq!(entry.visibility, Some(Visibility::HideSubtree));
+        assert!(!entry.stale);
+        fs::remove_dir_all(app.debug_info_file.parent().unwrap()).unwrap();
     }
 }
@@ -10,6 +10,7 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 /// Terminal events.
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug)]
 pub enum Event {
     /// Terminal tick.
@@ -3,64 +3,203 @@
 // Released under the MIT license.
 // see https://opensource.org/licenses/mit-license.php
 
-mod key;
-mod keybinding;
+pub mod key;
+pub mod keybinding;
 
+use self::key::Key;
+use self::keybinding::{
+    BookmarksAction, CommonAction, DiffAction, KeyBindings, NoteAction, PopupAction, RulesAction,
+    SearchAction, StatsAction, ViewAction, VisibilityAction,
+};
 use super::app::{App, AppMode};
-use crate::visibility_info::VisibilityInfo;
-use anyhow::{Context, Result};
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use std::fmt::Write;
-use std::fs;
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> Result<()> {
-    let key = key_event.into();
-    match key {
-        keybinding::common::QUIT_1 | keybinding::common::QUIT_2 => {
-            app.running = false;
+    let key = Key::from(key_event);
+    let common_action = app.key_bindings.common.action(key);
+    app.status_message.clear();
+    // Keys of the note editor precede the common ones so that e.g. `Esc` does not quit while writing.
+    if app.mode == AppMode::EditNote {
+        match app.key_bindings.note.action(key) {
+            Some(NoteAction::Finish) => {
+                app.finish_note();
+                return Ok(());
+            }
+            Some(NoteAction::Cancel) => {
+                app.cancel_note();
+                return Ok(());
+            }
+            Some(NoteAction::DelChar) => {
+                app.note_string.pop();
+                return Ok(());
+            }
+            None => (),
+        }
+    }
+    match common_action {
+        Some(CommonAction::Quit) => {
+            app.quit();
             return Ok(());
         }
-        keybinding::common::RELOAD => {
-            app.debug_info = fs::read_to_string(&app.debug_info_file)
-                .with_context(|| format!("failed to read {}", app.debug_info_file.display()))?;
-            app.visibility_info = VisibilityInfo::try_new(&app.visibility_info_file)?
-                .update_by_debug_info(&app.debug_info)?;
-            app.visibility_hash_map = app.visibility_info.clone().into();
+        Some(CommonAction::Reload) => {
+            app.reload()?;
+            return Ok(());
+        }
+        Some(CommonAction::Save) => {
+            app.save();
             return Ok(());
         }
         _ => (),
     }
+    if app.show_help {
+        match common_action {
+            Some(CommonAction::Help) => app.show_help = false,
+            Some(CommonAction::Up) => app.help_scroll_up(1),
+            Some(CommonAction::Down) => app.help_scroll_down(1),
+            Some(CommonAction::UpFast) => app.help_scroll_up(20),
+            Some(CommonAction::DownFast) => app.help_scroll_down(20),
+            Some(CommonAction::Top) => app.help_scroll_up(usize::MAX),
+            Some(CommonAction::Bottom) => app.help_scroll_down(usize::MAX),
+            _ => (),
+        }
+        return Ok(());
+    }
+    if !matches!(app.mode, AppMode::SearchVisibility | AppMode::EditNote)
+        && common_action == Some(CommonAction::Help)
+    {
+        app.open_help();
+        return Ok(());
+    }
     match app.mode {
-        AppMode::ViewDebug => match key {
-            keybinding::view::EDIT_MODE => app.mode_change(AppMode::EditVisibility),
-            keybinding::common::UP => app.scroll_up(1),
-            keybinding::common::DOWN => app.scroll_down(1),
-            keybinding::common::UP_FAST => app.scroll_up(20),
-            keybinding::common::DOWN_FAST => app.scroll_down(20),
-            keybinding::common::TOP => app.scroll_up(u16::MAX),
-            keybinding::common::BOTTOM => app.scroll_down(u16::MAX),
+        AppMode::ResolveConflict => {
+            if let Some(action) = app.key_bindings.conflict.action(key) {
+                app.resolve_conflict(action);
+            }
+        }
+        AppMode::ViewDebug => match (app.key_bindings.view.action(key), common_action) {
+            (Some(ViewAction::EditMode), _) => app.mode_change(AppMode::EditVisibility),
+            (Some(ViewAction::StatsMode), _) => app.open_stats(),
+            (Some(ViewAction::BookmarksMode), _) => app.mode_change(AppMode::ViewBookmarks),
+            (Some(ViewAction::DiffMode), _) => app.open_diff(),
+            (Some(ViewAction::Bookmark), _) => app.toggle_bookmark_at_top(),
+            (Some(ViewAction::Annotate), _) => app.annotate_at_top(),
+            (Some(ViewAction::Left), _) => app.scroll_left(1),
+            (Some(ViewAction::Right), _) => app.scroll_right(1),
+            (Some(ViewAction::LeftFast), _) => app.scroll_left(20),
+            (Some(ViewAction::RightFast), _) => app.scroll_right(20),
+            (Some(ViewAction::LineHead), _) => app.scroll_left(u16::MAX),
+            (Some(ViewAction::Wrap), _) => app.toggle_wrap(),
+            (Some(ViewAction::ToggleValues), _) => app.toggle_values(),
+            (Some(ViewAction::HideExpr), _) => app.hide_expr_at_top(),
+            (Some(ViewAction::Fold), _) => app.toggle_fold_at_top(),
+            (Some(ViewAction::Popup), _) => app.open_value_popup(),
+            (Some(ViewAction::AutoScroll), _) => app.toggle_auto_scroll(),
+            (_, Some(CommonAction::Up)) => app.scroll_up(1),
+            (_, Some(CommonAction::Down)) => app.scroll_down(1),
+            (_, Some(CommonAction::UpFast)) => app.scroll_up(20),
+            (_, Some(CommonAction::DownFast)) => app.scroll_down(20),
+            (_, Some(CommonAction::Top)) => app.scroll_up(u16::MAX),
+            (_, Some(CommonAction::Bottom)) => app.scroll_down(u16::MAX),
+            _ => (),
+        },
+        AppMode::ViewValue => match (app.key_bindings.popup.action(key), common_action) {
+            (Some(PopupAction::ViewMode), _) => app.mode_change(AppMode::ViewDebug),
+            (_, Some(CommonAction::Up)) => app.popup_scroll_up(1),
+            (_, Some(CommonAction::Down)) => app.popup_scroll_down(1),
+            (_, Some(CommonAction::UpFast)) => app.popup_scroll_up(20),
+            (_, Some(CommonAction::DownFast)) => app.popup_scroll_down(20),
+            (_, Some(CommonAction::Top)) => app.popup_scroll_up(u16::MAX),
+            (_, Some(CommonAction::Bottom)) => app.popup_scroll_down(u16::MAX),
+            _ => (),
+        },
+        AppMode::ViewStats => match (app.key_bindings.stats.action(key), common_action) {
+            (Some(StatsAction::ViewMode), _) => app.mode_change(AppMode::ViewDebug),
+            (Some(StatsAction::Jump), _) => app.jump_to_occurrence(),
+            (Some(action @ (StatsAction::SortColumn | StatsAction::ReverseSort)), _) => {
+                app.sort_stats(action)
+            }
+            (_, Some(CommonAction::Up)) => app.idx_stats_prev(1),
+            (_, Some(CommonAction::Down)) => app.idx_stats_next(1),
+            (_, Some(CommonAction::UpFast)) => app.idx_stats_prev(20),
+            (_, Some(CommonAction::DownFast)) => app.idx_stats_next(20),
+            (_, Some(CommonAction::Top)) => app.idx_stats_prev(usize::MAX),
+            (_, Some(CommonAction::Bottom)) => app.idx_stats_next(usize::MAX),
+            _ => (),
+        },
+        AppMode::ViewBookmarks => match (app.key_bindings.bookmarks.action(key), common_action) {
+            (Some(BookmarksAction::ViewMode), _) => app.mode_change(AppMode::ViewDebug),
+            (Some(BookmarksAction::Jump), _) => app.jump_to_bookmark(),
+            (Some(BookmarksAction::Annotate), _) => app.annotate_bookmark(),
+            (Some(BookmarksAction::Delete), _) => app.delete_bookmark(),
+            (_, Some(CommonAction::Up)) => app.idx_bookmark_prev(1),
+            (_, Some(CommonAction::Down)) => app.idx_bookmark_next(1),
+            (_, Some(CommonAction::UpFast)) => app.idx_bookmark_prev(20),
+            (_, Some(CommonAction::DownFast)) => app.idx_bookmark_next(20),
+            (_, Some(CommonAction::Top)) => app.idx_bookmark_prev(usize::MAX),
+            (_, Some(CommonAction::Bottom)) => app.idx_bookmark_next(usize::MAX),
+            _ => (),
+        },
+        AppMode::ViewDiff => match (app.key_bindings.diff.action(key), common_action) {
+            (Some(DiffAction::ViewMode), _) => app.mode_change(AppMode::ViewDebug),
+            (Some(DiffAction::Jump), _) => app.jump_to_diff_row(),
+            (Some(action @ (DiffAction::NextDifference | DiffAction::PrevDifference)), _) => {
+                app.move_to_difference(action)
+            }
+            (_, Some(CommonAction::Up)) => app.diff_scroll_up(1),
+            (_, Some(CommonAction::Down)) => app.diff_scroll_down(1),
+            (_, Some(CommonAction::UpFast)) => app.diff_scroll_up(20),
+            (_, Some(CommonAction::DownFast)) => app.diff_scroll_down(20),
+            (_, Some(CommonAction::Top)) => app.diff_scroll_up(usize::MAX),
+            (_, Some(CommonAction::Bottom)) => app.diff_scroll_down(usize::MAX),
+            _ => (),
+        },
+        AppMode::EditVisibility => match (app.key_bindings.visibility.action(key), common_action) {
+            (Some(VisibilityAction::ViewMode), _) => app.mode_change(AppMode::ViewDebug),
+            (Some(VisibilityAction::SearchMode), _) => app.mode_change(AppMode::SearchVisibility),
+            (Some(VisibilityAction::RulesMode), _) => app.mode_change(AppMode::EditRules),
+            (Some(VisibilityAction::Toggle), _) => app.update_visibility(),
+            (Some(VisibilityAction::Mode), _) => app.cycle_visibility_mode(),
+            (Some(VisibilityAction::Unset), _) => app.unset_visibility(),
+            (Some(VisibilityAction::AddLocations), _) => app.add_location_entries(),
+            (Some(VisibilityAction::AddRule), _) => app.add_rule_from_search(),
+            (Some(VisibilityAction::Prune), _) => app.prune_visibility_info(),
+            (Some(VisibilityAction::Profile), _) => app.next_profile()?,
+            (Some(VisibilityAction::Undo), _) => app.undo_visibility(),
+            (Some(VisibilityAction::Redo), _) => app.redo_visibility(),
+            (Some(VisibilityAction::Jump), _) => app.jump_to_visibility_occurrence(),
+            (Some(VisibilityAction::FilterValues), _) => app.filter_values_by_search(),
+            (Some(VisibilityAction::ShowExprs), _) => app.show_hidden_exprs(),
+            (Some(action), _) => app.update_visibility_of_matches(action),
+            (_, Some(CommonAction::Up)) => app.idx_visibility_prev(1),
+            (_, Some(CommonAction::Down)) => app.idx_visibility_next(1),
+            (_, Some(CommonAction::UpFast)) => app.idx_visibility_prev(20),
+            (_, Some(CommonAction::DownFast)) => app.idx_visibility_next(20),
+            (_, Some(CommonAction::Top)) => app.idx_visibility_prev(usize::MAX),
+            (_, Some(CommonAction::Bottom)) => app.idx_visibility_next(usize::MAX),
             _ => (),
         },
-        AppMode::EditVisibility => match key {
-            keybinding::visibility::VIEW_MODE => app.mode_change(AppMode::ViewDebug),
-            keybinding::visibility::SEARCH_MODE => app.mode_change(AppMode::SearchVisibility),
-            keybinding::common::UP => app.idx_visibility_prev(1),
-            keybinding::common::DOWN => app.idx_visibility_next(1),
-            keybinding::common::UP_FAST => app.idx_visibility_prev(20),
-            keybinding::common::DOWN_FAST => app.idx_visibility_next(20),
-            keybinding::common::TOP => app.idx_visibility_prev(usize::MAX),
-            keybinding::common::BOTTOM => app.idx_visibility_next(usize::MAX),
-            keybinding::visibility::TOGGLE => app.update_visibility(),
+        AppMode::EditRules => match (app.key_bindings.rules.action(key), common_action) {
+            (Some(RulesAction::VisibilityMode), _) => app.mode_change(AppMode::EditVisibility),
+            (Some(RulesAction::Undo), _) => app.undo_visibility(),
+            (Some(RulesAction::Redo), _) => app.redo_visibility(),
+            (Some(action), _) => app.update_rule(action),
+            (_, Some(CommonAction::Up)) => app.idx_rule_prev(1),
+            (_, Some(CommonAction::Down)) => app.idx_rule_next(1),
+            (_, Some(CommonAction::UpFast)) => app.idx_rule_prev(20),
+            (_, Some(CommonAction::DownFast)) => app.idx_rule_next(20),
+            (_, Some(CommonAction::Top)) => app.idx_rule_prev(usize::MAX),
+            (_, Some(CommonAction::Bottom)) => app.idx_rule_next(usize::MAX),
             _ => (),
         },
         AppMode::SearchVisibility => {
-            match key {
-                keybinding::search::EDIT_MODE => {
+            match app.key_bindings.search.action(key) {
+                Some(SearchAction::EditMode) => {
                     app.mode_change(AppMode::EditVisibility);
                     return Ok(());
                 }
-                keybinding::search::DEL_CHAR_1 | keybinding::search::DEL_CHAR_2 => {
+                Some(SearchAction::DelChar) => {
                     if app.search_string.is_empty() {
                         app.mode_change(AppMode::EditVisibility);
                     } else {
@@ -69,7 +208,7 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> Result<()> {
                     }
                     return Ok(());
                 }
-                _ => (),
+                None => (),
             }
             if let KeyCode::Char(c) = key.code {
                 if key.modifiers == KeyModifiers::NONE || key.modifiers == KeyModifiers::SHIFT {
@@ -79,87 +218,115 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> Result<()> {
                 }
             }
         }
+        AppMode::EditNote => {
+            if let KeyCode::Char(c) = key.code {
+                if key.modifiers == KeyModifiers::NONE || key.modifiers == KeyModifiers::SHIFT {
+                    app.note_string.push(c);
+                }
+            }
+        }
     }
     Ok(())
 }
 
-pub fn set_guidance(app_mode: AppMode, guidance: &mut String) {
-    guidance.clear();
-    write!(
-        guidance,
-        " Quit [{}, {}],",
-        keybinding::common::QUIT_1,
-        keybinding::common::QUIT_2
-    )
-    .unwrap();
-    write!(guidance, " Reload [{}],", keybinding::common::RELOAD,).unwrap();
-
-    match app_mode {
-        AppMode::ViewDebug => {
-            writeln!(
-                guidance,
-                " Open Visibility Editor [{}]",
-                keybinding::view::EDIT_MODE,
-            )
-            .unwrap();
+/// Handles the mouse events and updates the state of [`App`].
+pub fn handle_mouse_events(mouse_event: MouseEvent, app: &mut App) -> Result<()> {
+    let (column, row) = (mouse_event.column, mouse_event.row);
+    if app.show_help {
+        match mouse_event.kind {
+            MouseEventKind::ScrollUp => app.help_scroll_up(3),
+            MouseEventKind::ScrollDown => app.help_scroll_down(3),
+            _ => (),
         }
-        AppMode::EditVisibility => {
-            write!(
-                guidance,
-                " Close Visibility Editor [{}],",
-                keybinding::visibility::VIEW_MODE,
-            )
-            .unwrap();
-            writeln!(
-                guidance,
-                " Search [{}]",
-                keybinding::visibility::SEARCH_MODE,
-            )
-            .unwrap();
+        return Ok(());
+    }
+    match (app.mode, mouse_event.kind) {
+        (AppMode::ViewValue, MouseEventKind::ScrollUp) => app.popup_scroll_up(3),
+        (AppMode::ViewValue, MouseEventKind::ScrollDown) => app.popup_scroll_down(3),
+        (AppMode::ViewValue | AppMode::EditNote | AppMode::ResolveConflict, _) => (),
+        (_, MouseEventKind::ScrollUp) => {
+            if app.is_on_debug_info(column, row) {
+                app.scroll_up(3);
+            } else if app.is_on_visibility_info(column, row) {
+                app.idx_visibility_prev(3);
+            } else if app.is_on_stats(column, row) {
+                app.idx_stats_prev(3);
+            } else if app.is_on_bookmarks(column, row) {
+                app.idx_bookmark_prev(3);
+            } else if app.is_on_diff(column, row) {
+                app.diff_scroll_up(3);
+            } else if app.is_on_rules(column, row) {
+                app.idx_rule_prev(3);
+            }
         }
-        AppMode::SearchVisibility => {
-            write!(
-                guidance,
-                " Finish searching [{}]",
-                keybinding::search::EDIT_MODE,
-            )
-            .unwrap();
+        (_, MouseEventKind::ScrollDown) => {
+            if app.is_on_debug_info(column, row) {
+                app.scroll_down(3);
+            } else if app.is_on_visibility_info(column, row) {
+                app.idx_visibility_next(3);
+            } else if app.is_on_stats(column, row) {
+                app.idx_stats_next(3);
+            } else if app.is_on_bookmarks(column, row) {
+                app.idx_bookmark_next(3);
+            } else if app.is_on_diff(column, row) {
+                app.diff_scroll_down(3);
+            } else if app.is_on_rules(column, row) {
+                app.idx_rule_next(3);
+            }
         }
-    }
-
-    match app_mode {
-        AppMode::ViewDebug | AppMode::EditVisibility => {
-            write!(
-                guidance,
-                " Up/Down [{}/{}],",
-                keybinding::common::UP,
-                keybinding::common::DOWN
-            )
-            .unwrap();
-            write!(
-                guidance,
-                " Up/Down Fast [{}/{}],",
-                keybinding::common::UP_FAST,
-                keybinding::common::DOWN_FAST
-            )
-            .unwrap();
-            write!(
-                guidance,
-                " Top/Bottom [{}/{}]",
-                keybinding::common::TOP,
-                keybinding::common::BOTTOM
-            )
-            .unwrap();
+        (_, MouseEventKind::Down(MouseButton::Left)) => {
+            if app.is_on_debug_info(column, row) {
+                app.click_debug_info(row);
+            } else if app.is_on_visibility_info(column, row) {
+                if app.mode == AppMode::SearchVisibility {
+                    app.mode_change(AppMode::EditVisibility);
+                }
+                app.click_visibility_info(row);
+            } else if app.is_on_stats(column, row) {
+                app.click_stats(row);
+            } else if app.is_on_bookmarks(column, row) {
+                app.click_bookmarks(row);
+            } else if app.is_on_rules(column, row) {
+                app.click_rules(row);
+            }
         }
         _ => (),
     }
+    Ok(())
+}
 
-    if app_mode == AppMode::EditVisibility {
-        write!(
-            guidance,
-            ", Change Visibility [{}]",
-            keybinding::visibility::TOGGLE,
-        )
-        .unwrap();
+/// Returns pairs of keys and descriptions of the actions available in the mode.
+pub fn help(app_mode: AppMode, key_bindings: &KeyBindings) -> Vec<(String, &'static str)> {
+    let mut help = Vec::new();
+    if app_mode == AppMode::SearchVisibility {
+        help.extend(
+            key_bindings
+                .common
+                .help_of(&[CommonAction::Quit, CommonAction::Reload]),
+        );
+        help.extend(key_bindings.search.help());
+        help.push(("Others".to_string(), "Input the search regex"));
+    } else if app_mode == AppMode::EditNote {
+        help.extend(
+            key_bindings
+                .common
+                .help_of(&[CommonAction::Quit, CommonAction::Reload]),
+        );
+        help.extend(key_bindings.note.help());
+        help.push(("Others".to_string(), "Input the note"));
+    } else {
+        help.extend(key_bindings.common.help());
+        match app_mode {
+            AppMode::ViewDebug => help.extend(key_bindings.view.help()),
+            AppMode::EditVisibility => help.extend(key_bindings.visibility.help()),
+            AppMode::EditRules => help.extend(key_bindings.rules.help()),
+            AppMode::ViewValue => help.extend(key_bindings.popup.help()),
+            AppMode::ViewStats => help.extend(key_bindings.stats.help()),
+            AppMode::ViewBookmarks => help.extend(key_bindings.bookmarks.help()),
+            AppMode::ViewDiff => help.extend(key_bindings.diff.help()),
+            AppMode::ResolveConflict => help.extend(key_bindings.conflict.help()),
+            AppMode::SearchVisibility | AppMode::EditNote => unreachable!(),
+        }
     }
+    help
 }
@@ -3,10 +3,14 @@
 // Released under the MIT license.
 // see https://opensource.org/licenses/mit-license.php
 
+use anyhow::{bail, Error, Result};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MediaKeyCode, ModifierKeyCode};
+use serde_derive::Deserialize;
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
 pub struct Key {
     pub code: KeyCode,
     pub modifiers: KeyModifiers,
@@ -102,3 +106,121 @@ impl fmt::Display for Key {
         Ok(())
     }
 }
+
+/// Parses the same notation as that printed by [`Display`](fmt::Display).
+///
+/// An uppercase character implies `Shift-`, because terminals report it so.
+impl FromStr for Key {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        while let Some((prefix, modifier)) = [
+            ("Shift-", KeyModifiers::SHIFT),
+            ("Ctrl-", KeyModifiers::CONTROL),
+            ("Alt-", KeyModifiers::ALT),
+            ("Super-", KeyModifiers::SUPER),
+            ("Hyper-", KeyModifiers::HYPER),
+            ("Meta-", KeyModifiers::META),
+        ]
+        .into_iter()
+        .find(|(prefix, _)| rest.len() > prefix.len() && rest.starts_with(prefix))
+        {
+            modifiers |= modifier;
+            rest = &rest[prefix.len()..];
+        }
+        let code = match rest {
+            "BS" => KeyCode::Backspace,
+            "Enter" => KeyCode::Enter,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "PageUp" => KeyCode::PageUp,
+            "PageDown" => KeyCode::PageDown,
+            "Tab" => KeyCode::Tab,
+            "BackTab" => KeyCode::BackTab,
+            "Del" => KeyCode::Delete,
+            "Insert" => KeyCode::Insert,
+            "Null" => KeyCode::Null,
+            "Esc" => KeyCode::Esc,
+            "CapsLock" => KeyCode::CapsLock,
+            "ScrollLock" => KeyCode::ScrollLock,
+            "NumLock" => KeyCode::NumLock,
+            "PrintScreen" => KeyCode::PrintScreen,
+            "Pause" => KeyCode::Pause,
+            "Menu" => KeyCode::Menu,
+            "KeypadBegin" => KeyCode::KeypadBegin,
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => {
+                        if c.is_uppercase() {
+                            modifiers |= KeyModifiers::SHIFT;
+                        }
+                        KeyCode::Char(c)
+                    }
+                    (Some('F'), Some(_)) => match rest[1..].parse() {
+                        Ok(x) => KeyCode::F(x),
+                        Err(_) => bail!("unknown key: {}", s),
+                    },
+                    _ => bail!("unknown key: {}", s),
+                }
+            }
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = Error;
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for s in [
+            "k",
+            "Shift-G",
+            "Ctrl-k",
+            "Shift-Ctrl-Alt-Del",
+            "Ctrl--",
+            "-",
+            "?",
+            "Enter",
+            "BS",
+            "Esc",
+            "Tab",
+            "F12",
+            "Alt-PageDown",
+        ] {
+            let key = s.parse::<Key>().unwrap();
+            assert_eq!(key.to_string(), s);
+            assert_eq!(key.to_string().parse::<Key>().unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn uppercase_implies_shift() {
+        assert_eq!("G".parse::<Key>().unwrap(), "Shift-G".parse().unwrap());
+        assert_eq!(
+            Key::from(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            "G".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        for s in ["", "Foo", "Fx", "Ctrl-", "Shift-Foo"] {
+            assert!(s.parse::<Key>().is_err(), "{}", s);
+        }
+    }
+}
@@ -4,125 +4,725 @@
 // see https://opensource.org/licenses/mit-license.php
 
 use super::key::Key;
-use crossterm::event::{KeyCode, KeyModifiers};
+use anyhow::{anyhow, Context, Result};
+use serde::de::{Deserialize, DeserializeOwned, Deserializer, Error as _, IntoDeserializer};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
 
-pub mod common {
-    use super::*;
+/// Names of the presets of keybindings.
+pub const PRESETS: [&str; 2] = ["default", "alternative"];
 
-    pub const QUIT_1: Key = Key {
-        code: KeyCode::Esc,
-        modifiers: KeyModifiers::NONE,
-    };
-
-    pub const QUIT_2: Key = Key {
-        code: KeyCode::Char('c'),
-        modifiers: KeyModifiers::CONTROL,
-    };
-
-    pub const RELOAD: Key = Key {
-        code: KeyCode::Char('r'),
-        modifiers: KeyModifiers::CONTROL,
-    };
-
-    pub const TOP: Key = Key {
-        code: KeyCode::Char('g'),
-        modifiers: KeyModifiers::NONE,
-    };
-    pub const BOTTOM: Key = Key {
-        code: KeyCode::Char('G'),
-        modifiers: KeyModifiers::SHIFT,
-    };
-
-    #[cfg(not(feature = "alternative_keybinding"))]
-    pub const UP: Key = Key {
-        code: KeyCode::Char('k'),
-        modifiers: KeyModifiers::NONE,
-    };
-    #[cfg(feature = "alternative_keybinding")]
-    pub const UP: Key = Key {
-        code: KeyCode::Char('i'),
-        modifiers: KeyModifiers::NONE,
-    };
-
-    #[cfg(not(feature = "alternative_keybinding"))]
-    pub const DOWN: Key = Key {
-        code: KeyCode::Char('j'),
-        modifiers: KeyModifiers::NONE,
-    };
-    #[cfg(feature = "alternative_keybinding")]
-    pub const DOWN: Key = Key {
-        code: KeyCode::Char('k'),
-        modifiers: KeyModifiers::NONE,
-    };
-
-    #[cfg(not(feature = "alternative_keybinding"))]
-    pub const UP_FAST: Key = Key {
-        code: KeyCode::Char('k'),
-        modifiers: KeyModifiers::CONTROL,
-    };
-    #[cfg(feature = "alternative_keybinding")]
-    pub const UP_FAST: Key = Key {
-        code: KeyCode::Tab,
-        modifiers: KeyModifiers::NONE,
-        // code: KeyCode::Char('i'),
-        // modifiers: KeyModifiers::CONTROL,
-    };
-
-    #[cfg(not(feature = "alternative_keybinding"))]
-    pub const DOWN_FAST: Key = Key {
-        code: KeyCode::Char('j'),
-        modifiers: KeyModifiers::CONTROL,
-    };
-    #[cfg(feature = "alternative_keybinding")]
-    pub const DOWN_FAST: Key = Key {
-        code: KeyCode::Char('k'),
-        modifiers: KeyModifiers::CONTROL,
-    };
-}
-
-pub mod view {
-    use super::*;
+/// Action triggered by keys.
+pub trait Action: Copy + Eq + Hash + 'static {
+    /// All the actions in the order shown in the help.
+    const ALL: &'static [Self];
+    fn description(self) -> &'static str;
+}
 
-    pub const EDIT_MODE: Key = Key {
-        code: KeyCode::Char('v'),
-        modifiers: KeyModifiers::NONE,
-    };
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommonAction {
+    Quit,
+    Reload,
+    Save,
+    Help,
+    Up,
+    Down,
+    UpFast,
+    DownFast,
+    Top,
+    Bottom,
 }
 
-pub mod visibility {
-    use super::*;
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewAction {
+    EditMode,
+    StatsMode,
+    BookmarksMode,
+    DiffMode,
+    Bookmark,
+    Annotate,
+    AutoScroll,
+    Popup,
+    Fold,
+    Wrap,
+    ToggleValues,
+    HideExpr,
+    Left,
+    Right,
+    LeftFast,
+    RightFast,
+    LineHead,
+}
 
-    pub const TOGGLE: Key = Key {
-        code: KeyCode::Enter,
-        modifiers: KeyModifiers::NONE,
-    };
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisibilityAction {
+    ViewMode,
+    SearchMode,
+    RulesMode,
+    Toggle,
+    Mode,
+    Unset,
+    AddLocations,
+    AddRule,
+    Prune,
+    Profile,
+    ShowAll,
+    HideAll,
+    Invert,
+    Undo,
+    Redo,
+    Jump,
+    FilterValues,
+    ShowExprs,
+}
 
-    pub const VIEW_MODE: Key = Key {
-        code: KeyCode::Char('v'),
-        modifiers: KeyModifiers::NONE,
-    };
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesAction {
+    VisibilityMode,
+    Toggle,
+    Mode,
+    Syntax,
+    Delete,
+    Raise,
+    Lower,
+    Undo,
+    Redo,
+}
 
-    pub const SEARCH_MODE: Key = Key {
-        code: KeyCode::Char('/'),
-        modifiers: KeyModifiers::NONE,
-    };
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchAction {
+    EditMode,
+    DelChar,
 }
 
-pub mod search {
-    use super::*;
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsAction {
+    ViewMode,
+    Jump,
+    SortColumn,
+    ReverseSort,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookmarksAction {
+    ViewMode,
+    Jump,
+    Annotate,
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffAction {
+    ViewMode,
+    Jump,
+    NextDifference,
+    PrevDifference,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteAction {
+    Finish,
+    Cancel,
+    DelChar,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PopupAction {
+    ViewMode,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictAction {
+    Merge,
+    Overwrite,
+    Cancel,
+}
+
+impl Action for CommonAction {
+    const ALL: &'static [Self] = &[
+        Self::Quit,
+        Self::Reload,
+        Self::Save,
+        Self::Help,
+        Self::Up,
+        Self::Down,
+        Self::UpFast,
+        Self::DownFast,
+        Self::Top,
+        Self::Bottom,
+    ];
+    fn description(self) -> &'static str {
+        match self {
+            Self::Quit => "Save and quit",
+            Self::Reload => "Reload debug_info and visibility_info",
+            Self::Save => "Save visibility_info and bookmarks",
+            Self::Help => "Show/hide this help",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::UpFast => "Up fast",
+            Self::DownFast => "Down fast",
+            Self::Top => "Top",
+            Self::Bottom => "Bottom",
+        }
+    }
+}
+
+impl Action for ViewAction {
+    const ALL: &'static [Self] = &[
+        Self::EditMode,
+        Self::StatsMode,
+        Self::BookmarksMode,
+        Self::DiffMode,
+        Self::Bookmark,
+        Self::Annotate,
+        Self::AutoScroll,
+        Self::Popup,
+        Self::Fold,
+        Self::Wrap,
+        Self::ToggleValues,
+        Self::HideExpr,
+        Self::Left,
+        Self::Right,
+        Self::LeftFast,
+        Self::RightFast,
+        Self::LineHead,
+    ];
+    fn description(self) -> &'static str {
+        match self {
+            Self::EditMode => "Open the visibility editor",
+            Self::StatsMode => "Open the call statistics",
+            Self::BookmarksMode => "Open the bookmarks",
+            Self::DiffMode => "Open the diff with the trace given by --diff",
+            Self::Bookmark => "Bookmark/unbookmark the line at the top",
+            Self::Annotate => "Write a note on the line at the top",
+            Self::AutoScroll => "Enable/disable auto-scroll in the follow mode",
+            Self::Popup => "Open the value line at the top",
+            Self::Fold => "Fold/unfold the call at the top",
+            Self::Wrap => "Wrap/unwrap long lines",
+            Self::ToggleValues => "Show/hide all value lines",
+            Self::HideExpr => "Hide value lines of the expression at the top",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::LeftFast => "Left fast",
+            Self::RightFast => "Right fast",
+            Self::LineHead => "Head of lines",
+        }
+    }
+}
+
+impl Action for VisibilityAction {
+    const ALL: &'static [Self] = &[
+        Self::ViewMode,
+        Self::SearchMode,
+        Self::RulesMode,
+        Self::Toggle,
+        Self::Mode,
+        Self::Unset,
+        Self::AddLocations,
+        Self::AddRule,
+        Self::Prune,
+        Self::Profile,
+        Self::ShowAll,
+        Self::HideAll,
+        Self::Invert,
+        Self::Undo,
+        Self::Redo,
+        Self::Jump,
+        Self::FilterValues,
+        Self::ShowExprs,
+    ];
+    fn description(self) -> &'static str {
+        match self {
+            Self::ViewMode => "Close the visibility editor",
+            Self::SearchMode => "Search functions",
+            Self::RulesMode => "Open the rules",
+            Self::Toggle => "Change visibility",
+            Self::Mode => "Switch among show, hide, hide frame and collapse",
+            Self::Unset => "Leave visibility to rules",
+            Self::AddLocations => "Add an entry for each location of the function",
+            Self::AddRule => "Add a rule which hides functions matching the search regex",
+            Self::Prune => "Remove functions which are not found in debug_info",
+            Self::Profile => "Switch to the next profile",
+            Self::ShowAll => "Show all the functions matching the search regex",
+            Self::HideAll => "Hide all the functions matching the search regex",
+            Self::Invert => "Invert visibility of the functions matching the search regex",
+            Self::Undo => "Undo the last edit of visibility",
+            Self::Redo => "Redo the last undone edit of visibility",
+            Self::Jump => "Jump to the first or next call of the function",
+            Self::FilterValues => "Show only value lines matching the search regex",
+            Self::ShowExprs => "Show value lines of all the hidden expressions",
+        }
+    }
+}
+
+impl Action for RulesAction {
+    const ALL: &'static [Self] = &[
+        Self::VisibilityMode,
+        Self::Toggle,
+        Self::Mode,
+        Self::Syntax,
+        Self::Delete,
+        Self::Raise,
+        Self::Lower,
+        Self::Undo,
+        Self::Redo,
+    ];
+    fn description(self) -> &'static str {
+        match self {
+            Self::VisibilityMode => "Close the rules",
+            Self::Toggle => "Change visibility",
+            Self::Mode => "Switch among show, hide, hide frame and collapse",
+            Self::Syntax => "Switch the syntax between glob and regex",
+            Self::Delete => "Delete the rule",
+            Self::Raise => "Evaluate the rule earlier",
+            Self::Lower => "Evaluate the rule later",
+            Self::Undo => "Undo the last edit of visibility",
+            Self::Redo => "Redo the last undone edit of visibility",
+        }
+    }
+}
+
+impl Action for SearchAction {
+    const ALL: &'static [Self] = &[Self::EditMode, Self::DelChar];
+    fn description(self) -> &'static str {
+        match self {
+            Self::EditMode => "Finish searching",
+            Self::DelChar => "Delete a character",
+        }
+    }
+}
+
+impl Action for StatsAction {
+    const ALL: &'static [Self] = &[
+        Self::ViewMode,
+        Self::Jump,
+        Self::SortColumn,
+        Self::ReverseSort,
+    ];
+    fn description(self) -> &'static str {
+        match self {
+            Self::ViewMode => "Close the call statistics",
+            Self::Jump => "Jump to the next call of the function",
+            Self::SortColumn => "Sort by the next column",
+            Self::ReverseSort => "Reverse the order",
+        }
+    }
+}
 
-    pub const EDIT_MODE: Key = Key {
-        code: KeyCode::Enter,
-        modifiers: KeyModifiers::NONE,
-    };
+impl Action for BookmarksAction {
+    const ALL: &'static [Self] = &[Self::ViewMode, Self::Jump, Self::Annotate, Self::Delete];
+    fn description(self) -> &'static str {
+        match self {
+            Self::ViewMode => "Close the bookmarks",
+            Self::Jump => "Jump to the bookmarked line",
+            Self::Annotate => "Write a note on the bookmarked line",
+            Self::Delete => "Delete the bookmark",
+        }
+    }
+}
+
+impl Action for DiffAction {
+    const ALL: &'static [Self] = &[
+        Self::ViewMode,
+        Self::Jump,
+        Self::NextDifference,
+        Self::PrevDifference,
+    ];
+    fn description(self) -> &'static str {
+        match self {
+            Self::ViewMode => "Close the diff",
+            Self::Jump => "Jump to the line of the first trace at the top",
+            Self::NextDifference => "Next difference",
+            Self::PrevDifference => "Previous difference",
+        }
+    }
+}
+
+impl Action for NoteAction {
+    const ALL: &'static [Self] = &[Self::Finish, Self::Cancel, Self::DelChar];
+    fn description(self) -> &'static str {
+        match self {
+            Self::Finish => "Finish writing the note",
+            Self::Cancel => "Discard the note being written",
+            Self::DelChar => "Delete a character",
+        }
+    }
+}
+
+impl Action for PopupAction {
+    const ALL: &'static [Self] = &[Self::ViewMode];
+    fn description(self) -> &'static str {
+        match self {
+            Self::ViewMode => "Close the value",
+        }
+    }
+}
+
+impl Action for ConflictAction {
+    const ALL: &'static [Self] = &[Self::Merge, Self::Overwrite, Self::Cancel];
+    fn description(self) -> &'static str {
+        match self {
+            Self::Merge => "Merge the changes in the file and save",
+            Self::Overwrite => "Overwrite the file",
+            Self::Cancel => "Cancel saving",
+        }
+    }
+}
+
+/// Keys bound to each action.
+#[derive(Clone, Debug)]
+pub struct Bindings<A: Eq + Hash>(HashMap<A, Vec<Key>>);
+
+/// Keybindings of all modes.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    pub common: Bindings<CommonAction>,
+    pub view: Bindings<ViewAction>,
+    pub visibility: Bindings<VisibilityAction>,
+    pub rules: Bindings<RulesAction>,
+    pub search: Bindings<SearchAction>,
+    pub stats: Bindings<StatsAction>,
+    pub bookmarks: Bindings<BookmarksAction>,
+    pub diff: Bindings<DiffAction>,
+    pub note: Bindings<NoteAction>,
+    pub popup: Bindings<PopupAction>,
+    pub conflict: Bindings<ConflictAction>,
+}
+
+/// Contents of a keybindings file.
+///
+/// Actions which are not written keep the keys of the preset.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyBindingsFile {
+    preset: Option<String>,
+    common: Option<Bindings<CommonAction>>,
+    view: Option<Bindings<ViewAction>>,
+    visibility: Option<Bindings<VisibilityAction>>,
+    rules: Option<Bindings<RulesAction>>,
+    search: Option<Bindings<SearchAction>>,
+    stats: Option<Bindings<StatsAction>>,
+    bookmarks: Option<Bindings<BookmarksAction>>,
+    diff: Option<Bindings<DiffAction>>,
+    note: Option<Bindings<NoteAction>>,
+    popup: Option<Bindings<PopupAction>>,
+    conflict: Option<Bindings<ConflictAction>>,
+}
+
+/// A key bound to more than one action in a table is an error.
+impl<'de, A: DeserializeOwned + Eq + Hash> Deserialize<'de> for Bindings<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bindings = HashMap::<String, Vec<Key>>::deserialize(deserializer)?;
+        let mut actions = HashMap::new();
+        for (action, keys) in bindings.iter() {
+            for key in keys {
+                if let Some(other) = actions.insert(*key, action) {
+                    if other != action {
+                        return Err(D::Error::custom(format!(
+                            "{} is bound to both {} and {}",
+                            key,
+                            std::cmp::min(action, other),
+                            std::cmp::max(action, other)
+                        )));
+                    }
+                }
+            }
+        }
+        bindings
+            .into_iter()
+            .map(|(action, keys)| {
+                A::deserialize(IntoDeserializer::<D::Error>::into_deserializer(action))
+                    .map(|action| (action, keys))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl<A: Action> Bindings<A> {
+    fn new<const N: usize>(bindings: [(A, &[&str]); N]) -> Self {
+        Self(
+            bindings
+                .into_iter()
+                .map(|(action, keys)| {
+                    (
+                        action,
+                        keys.iter().map(|key| key.parse().unwrap()).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Binds the keys of `other`, which are removed from the other actions.
+    fn overwrite(&mut self, other: Self) {
+        for keys in self.0.values_mut() {
+            keys.retain(|key| !other.0.values().any(|other_keys| other_keys.contains(key)));
+        }
+        self.0.extend(other.0);
+    }
+
+    /// Returns the action bound to the key.
+    pub fn action(&self, key: Key) -> Option<A> {
+        self.0
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    /// Returns the keys bound to the action.
+    pub fn keys(&self, action: A) -> &[Key] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the keys bound to the action joined with `", "`.
+    pub fn keys_string(&self, action: A) -> String {
+        self.keys(action)
+            .iter()
+            .map(Key::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns pairs of keys and descriptions of the actions.
+    pub fn help_of(&self, actions: &[A]) -> Vec<(String, &'static str)> {
+        actions
+            .iter()
+            .map(|action| (self.keys_string(*action), action.description()))
+            .collect()
+    }
+
+    /// Returns pairs of keys and descriptions of all the actions.
+    pub fn help(&self) -> Vec<(String, &'static str)> {
+        self.help_of(A::ALL)
+    }
+}
+
+impl KeyBindings {
+    pub fn preset(name: &str) -> Option<Self> {
+        let [up, down, up_fast, down_fast, left, left_fast]: [&[&str]; 6] = match name {
+            "default" => [
+                &["k"],
+                &["j"],
+                &["Ctrl-k"],
+                &["Ctrl-j"],
+                &["h"],
+                &["Shift-H"],
+            ],
+            "alternative" => [&["i"], &["k"], &["Tab"], &["Ctrl-k"], &["j"], &["Shift-J"]],
+            _ => return None,
+        };
+        Some(Self {
+            common: Bindings::new([
+                (CommonAction::Quit, &["Esc", "Ctrl-c"]),
+                (CommonAction::Reload, &["Ctrl-r"]),
+                (CommonAction::Save, &["Ctrl-s"]),
+                (CommonAction::Help, &["?"]),
+                (CommonAction::Top, &["g"]),
+                (CommonAction::Bottom, &["Shift-G"]),
+                (CommonAction::Up, up),
+                (CommonAction::Down, down),
+                (CommonAction::UpFast, up_fast),
+                (CommonAction::DownFast, down_fast),
+            ]),
+            view: Bindings::new([
+                (ViewAction::EditMode, &["v"]),
+                (ViewAction::StatsMode, &["s"]),
+                (ViewAction::BookmarksMode, &["b"]),
+                (ViewAction::DiffMode, &["d"]),
+                (ViewAction::Bookmark, &["m"]),
+                (ViewAction::Annotate, &["a"]),
+                (ViewAction::AutoScroll, &["f"]),
+                (ViewAction::Popup, &["Enter"]),
+                (ViewAction::Fold, &["z"]),
+                (ViewAction::Wrap, &["w"]),
+                (ViewAction::ToggleValues, &["Shift-V"]),
+                (ViewAction::HideExpr, &["x"]),
+                (ViewAction::Left, left),
+                (ViewAction::Right, &["l"]),
+                (ViewAction::LeftFast, left_fast),
+                (ViewAction::RightFast, &["Shift-L"]),
+                (ViewAction::LineHead, &["0"]),
+            ]),
+            visibility: Bindings::new([
+                (VisibilityAction::Toggle, &["Enter"]),
+                (VisibilityAction::ViewMode, &["v"]),
+                (VisibilityAction::SearchMode, &["/"]),
+                (VisibilityAction::RulesMode, &["r"]),
+                (VisibilityAction::Mode, &["m"]),
+                (VisibilityAction::Unset, &["x"]),
+                (VisibilityAction::AddLocations, &["l"]),
+                (VisibilityAction::AddRule, &["a"]),
+                (VisibilityAction::Prune, &["Shift-P"]),
+                (VisibilityAction::Profile, &["p"]),
+                (VisibilityAction::ShowAll, &["Shift-S"]),
+                (VisibilityAction::HideAll, &["Shift-H"]),
+                (VisibilityAction::Invert, &["Shift-I"]),
+                (VisibilityAction::Undo, &["u"]),
+                (VisibilityAction::Redo, &["Shift-U"]),
+                (VisibilityAction::Jump, &["o"]),
+                (VisibilityAction::FilterValues, &["f"]),
+                (VisibilityAction::ShowExprs, &["Shift-X"]),
+            ]),
+            rules: Bindings::new([
+                (RulesAction::VisibilityMode, &["r"]),
+                (RulesAction::Toggle, &["Enter"]),
+                (RulesAction::Mode, &["m"]),
+                (RulesAction::Syntax, &["t"]),
+                (RulesAction::Delete, &["d"]),
+                (RulesAction::Raise, &["Shift-K"]),
+                (RulesAction::Lower, &["Shift-J"]),
+                (RulesAction::Undo, &["u"]),
+                (RulesAction::Redo, &["Shift-U"]),
+            ]),
+            search: Bindings::new([
+                (SearchAction::EditMode, &["Enter"]),
+                (SearchAction::DelChar, &["BS", "Ctrl-h"]),
+            ]),
+            stats: Bindings::new([
+                (StatsAction::ViewMode, &["s"]),
+                (StatsAction::Jump, &["Enter"]),
+                (StatsAction::SortColumn, &["o"]),
+                (StatsAction::ReverseSort, &["Shift-O"]),
+            ]),
+            bookmarks: Bindings::new([
+                (BookmarksAction::ViewMode, &["b"]),
+                (BookmarksAction::Jump, &["Enter"]),
+                (BookmarksAction::Annotate, &["a"]),
+                (BookmarksAction::Delete, &["d"]),
+            ]),
+            diff: Bindings::new([
+                (DiffAction::ViewMode, &["d"]),
+                (DiffAction::Jump, &["Enter"]),
+                (DiffAction::NextDifference, &["n"]),
+                (DiffAction::PrevDifference, &["Shift-N"]),
+            ]),
+            note: Bindings::new([
+                (NoteAction::Finish, &["Enter"]),
+                (NoteAction::Cancel, &["Esc"]),
+                (NoteAction::DelChar, &["BS", "Ctrl-h"]),
+            ]),
+            popup: Bindings::new([(PopupAction::ViewMode, &["Enter"])]),
+            conflict: Bindings::new([
+                (ConflictAction::Merge, &["m"]),
+                (ConflictAction::Overwrite, &["o"]),
+                (ConflictAction::Cancel, &["c"]),
+            ]),
+        })
+    }
+
+    /// Reads keybindings from a TOML file if it exists.
+    ///
+    /// If the file does not exist, the default preset is used.
+    pub fn try_new(file: &Path) -> Result<Self> {
+        if file.is_file() {
+            Self::read_toml_file(file)
+        } else {
+            Ok(Self::preset(PRESETS[0]).unwrap())
+        }
+    }
+
+    pub fn read_toml_file(file: &Path) -> Result<Self> {
+        let key_bindings_file: KeyBindingsFile = toml::de::from_str(
+            &fs::read_to_string(file)
+                .with_context(|| format!("failed to read {}", file.display()))?,
+        )
+        .with_context(|| format!("failed to parse {}", file.display()))?;
+        let preset = key_bindings_file.preset.as_deref().unwrap_or(PRESETS[0]);
+        let mut key_bindings = Self::preset(preset).ok_or_else(|| {
+            anyhow!(
+                "unknown preset of keybindings: {} (available: {})",
+                preset,
+                PRESETS.join(", ")
+            )
+        })?;
+        if let Some(common) = key_bindings_file.common {
+            key_bindings.common.overwrite(common);
+        }
+        if let Some(view) = key_bindings_file.view {
+            key_bindings.view.overwrite(view);
+        }
+        if let Some(visibility) = key_bindings_file.visibility {
+            key_bindings.visibility.overwrite(visibility);
+        }
+        if let Some(rules) = key_bindings_file.rules {
+            key_bindings.rules.overwrite(rules);
+        }
+        if let Some(search) = key_bindings_file.search {
+            key_bindings.search.overwrite(search);
+        }
+        if let Some(stats) = key_bindings_file.stats {
+            key_bindings.stats.overwrite(stats);
+        }
+        if let Some(bookmarks) = key_bindings_file.bookmarks {
+            key_bindings.bookmarks.overwrite(bookmarks);
+        }
+        if let Some(diff) = key_bindings_file.diff {
+            key_bindings.diff.overwrite(diff);
+        }
+        if let Some(note) = key_bindings_file.note {
+            key_bindings.note.overwrite(note);
+        }
+        if let Some(popup) = key_bindings_file.popup {
+            key_bindings.popup.overwrite(popup);
+        }
+        if let Some(conflict) = key_bindings_file.conflict {
+            key_bindings.conflict.overwrite(conflict);
+        }
+        Ok(key_bindings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub const DEL_CHAR_1: Key = Key {
-        code: KeyCode::Backspace,
-        modifiers: KeyModifiers::NONE,
-    };
+    #[test]
+    fn keys_of_file_replace_those_of_preset() {
+        let key_bindings_file = toml::from_str::<KeyBindingsFile>(
+            r#"
+            [view]
+            fold = ["d"]
+            "#,
+        )
+        .unwrap();
+        let mut view = KeyBindings::preset("default").unwrap().view;
+        view.overwrite(key_bindings_file.view.unwrap());
+        let key = "d".parse().unwrap();
+        assert_eq!(view.action(key), Some(ViewAction::Fold));
+        assert!(!view.keys(ViewAction::DiffMode).contains(&key));
+    }
 
-    pub const DEL_CHAR_2: Key = Key {
-        code: KeyCode::Char('h'),
-        modifiers: KeyModifiers::CONTROL,
-    };
+    #[test]
+    fn key_bound_to_two_actions_is_error() {
+        assert!(toml::from_str::<KeyBindingsFile>(
+            r#"
+            [view]
+            fold = ["z"]
+            wrap = ["w", "z"]
+            "#,
+        )
+        .is_err());
+        assert!(toml::from_str::<KeyBindingsFile>(
+            r#"
+            [view]
+            fold = ["z", "z"]
+            "#,
+        )
+        .is_ok());
+    }
 }
@@ -0,0 +1,254 @@
+// Copyright (c) 2023 Yuichi Ishida <yu1guana@gmail.com>
+//
+// Released under the MIT license.
+// see https://opensource.org/licenses/mit-license.php
+
+use crate::debug_info::LineKind;
+use anyhow::{bail, Context, Error, Result};
+use regex::Regex;
+use serde_derive::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use tui::style::{Color, Modifier, Style};
+
+/// Colors used to color functions by module.
+const MODULE_PALETTE: [Color; 10] = [
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+    Color::Magenta,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+];
+
+/// Colors and highlighting rules.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Line numbers and depth markers.
+    pub depth: Color,
+    /// Function names.
+    pub func: Color,
+    /// Value lines.
+    pub value: Color,
+    /// Error messages.
+    pub error: Color,
+    /// Hidden entries of visibility information.
+    pub hidden: Color,
+    /// Inactive widgets.
+    pub inactive: Color,
+    /// Cursor of the search box.
+    pub cursor: Color,
+    /// Bookmark markers and notes.
+    pub bookmark: Color,
+    /// Lines only in the first trace of a diff.
+    pub removed: Color,
+    /// Lines only in the second trace of a diff.
+    pub added: Color,
+    /// Value lines which differ between traces.
+    pub changed: Color,
+    /// Groups of the visibility editor where only some functions are shown.
+    pub partial: Color,
+    pub color_by_module: bool,
+    pub highlight_rules: Vec<HighlightRule>,
+}
+
+/// Style applied to functions whose names match the regex.
+#[derive(Clone, Debug)]
+pub struct HighlightRule {
+    pub regex: Regex,
+    pub style: Style,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct ColorName(Color);
+
+/// Contents of a theme file.
+///
+/// Colors which are not written keep the default ones.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    depth: Option<ColorName>,
+    func: Option<ColorName>,
+    value: Option<ColorName>,
+    error: Option<ColorName>,
+    hidden: Option<ColorName>,
+    inactive: Option<ColorName>,
+    cursor: Option<ColorName>,
+    bookmark: Option<ColorName>,
+    removed: Option<ColorName>,
+    added: Option<ColorName>,
+    changed: Option<ColorName>,
+    partial: Option<ColorName>,
+    #[serde(default)]
+    color_by_module: bool,
+    #[serde(default)]
+    highlight: Vec<HighlightRuleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HighlightRuleEntry {
+    pattern: String,
+    fg: Option<ColorName>,
+    bg: Option<ColorName>,
+    #[serde(default)]
+    bold: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            depth: Color::DarkGray,
+            func: Color::Reset,
+            value: Color::Cyan,
+            error: Color::Red,
+            hidden: Color::DarkGray,
+            inactive: Color::DarkGray,
+            cursor: Color::Gray,
+            bookmark: Color::LightYellow,
+            removed: Color::Red,
+            added: Color::Green,
+            changed: Color::Yellow,
+            partial: Color::LightYellow,
+            color_by_module: false,
+            highlight_rules: Vec::new(),
+        }
+    }
+}
+
+impl TryFrom<String> for ColorName {
+    type Error = Error;
+    fn try_from(name: String) -> Result<Self> {
+        let normalized = name.to_ascii_lowercase().replace(['_', '-', ' '], "");
+        let color = match normalized.as_str() {
+            "reset" => Color::Reset,
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "gray" => Color::Gray,
+            "darkgray" => Color::DarkGray,
+            "lightred" => Color::LightRed,
+            "lightgreen" => Color::LightGreen,
+            "lightyellow" => Color::LightYellow,
+            "lightblue" => Color::LightBlue,
+            "lightmagenta" => Color::LightMagenta,
+            "lightcyan" => Color::LightCyan,
+            "white" => Color::White,
+            _ => {
+                if let Some(hex) = normalized.strip_prefix('#') {
+                    match u32::from_str_radix(hex, 16) {
+                        Ok(rgb) if hex.len() == 6 => {
+                            Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+                        }
+                        _ => bail!("invalid color: {}", name),
+                    }
+                } else if let Ok(index) = normalized.parse() {
+                    Color::Indexed(index)
+                } else {
+                    bail!("invalid color: {}", name)
+                }
+            }
+        };
+        Ok(Self(color))
+    }
+}
+
+impl Theme {
+    /// Reads a theme from a TOML file if it exists.
+    ///
+    /// If the file does not exist, the default theme is used.
+    pub fn try_new(file: &Path) -> Result<Self> {
+        if file.is_file() {
+            Self::read_toml_file(file)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn read_toml_file(file: &Path) -> Result<Self> {
+        let theme_file: ThemeFile = toml::de::from_str(
+            &fs::read_to_string(file)
+                .with_context(|| format!("failed to read {}", file.display()))?,
+        )
+        .with_context(|| format!("failed to parse {}", file.display()))?;
+        let default = Self::default();
+        let color = |color: Option<ColorName>, default| color.map_or(default, |color| color.0);
+        Ok(Self {
+            depth: color(theme_file.depth, default.depth),
+            func: color(theme_file.func, default.func),
+            value: color(theme_file.value, default.value),
+            error: color(theme_file.error, default.error),
+            hidden: color(theme_file.hidden, default.hidden),
+            inactive: color(theme_file.inactive, default.inactive),
+            cursor: color(theme_file.cursor, default.cursor),
+            bookmark: color(theme_file.bookmark, default.bookmark),
+            removed: color(theme_file.removed, default.removed),
+            added: color(theme_file.added, default.added),
+            changed: color(theme_file.changed, default.changed),
+            partial: color(theme_file.partial, default.partial),
+            color_by_module: theme_file.color_by_module,
+            highlight_rules: theme_file
+                .highlight
+                .into_iter()
+                .map(|entry| {
+                    let mut style = Style::default();
+                    if let Some(fg) = entry.fg {
+                        style = style.fg(fg.0);
+                    }
+                    if let Some(bg) = entry.bg {
+                        style = style.bg(bg.0);
+                    }
+                    if entry.bold {
+                        style = style.add_modifier(Modifier::BOLD);
+                    }
+                    Regex::new(&entry.pattern)
+                        .map(|regex| HighlightRule { regex, style })
+                        .with_context(|| {
+                            format!("invalid pattern in {}: {}", file.display(), entry.pattern)
+                        })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Returns the style of the text displayed for a line.
+    pub fn content_style(&self, content: &str, kind: LineKind) -> Style {
+        match kind {
+            LineKind::FuncEnter => self.func_style(content),
+            _ => Style::default().fg(self.value),
+        }
+    }
+
+    /// Returns the style of a function name.
+    ///
+    /// The first matching highlighting rule wins over coloring by module.
+    pub fn func_style(&self, func_name: &str) -> Style {
+        if let Some(rule) = self
+            .highlight_rules
+            .iter()
+            .find(|rule| rule.regex.is_match(func_name))
+        {
+            return rule.style;
+        }
+        match func_name.rsplit_once("::") {
+            Some((module, _)) if self.color_by_module => {
+                let mut hasher = DefaultHasher::new();
+                module.hash(&mut hasher);
+                Style::default().fg(MODULE_PALETTE[hasher.finish() as usize % MODULE_PALETTE.len()])
+            }
+            _ => Style::default().fg(self.func),
+        }
+    }
+}
@@ -3,32 +3,271 @@
 // Released under the MIT license.
 // see https://opensource.org/licenses/mit-license.php
 
-use anyhow::{bail, Context, Result};
+use crate::debug_info::{self, CallTree};
+use anyhow::{bail, Context, Error, Result};
 use proctrack::funclog::methodlog;
 use proctrack::typename_derive::TypeName;
+use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 use std::cmp::{Ord, Ordering, PartialOrd};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::{self, File};
+use std::io::{self, Write};
 use std::ops::{Deref, DerefMut};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How a call of a function is displayed.
+///
+/// It is written as `true` or `false` for `Show` or `HideSubtree` in the same way as older versions.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "VisibilityValue", into = "VisibilityValue")]
+pub enum Visibility {
+    #[default]
+    Show,
+    /// The call and all the lines beneath it are hidden.
+    HideSubtree,
+    /// Only the call and its value lines are hidden, and its callees are shown one level up.
+    HideFrame,
+    /// The call is shown but the lines beneath it are folded.
+    Collapse,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum VisibilityValue {
+    Bool(bool),
+    Name(String),
+}
 
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize, TypeName)]
 pub struct VisibilityEntry {
     pub func_name: String,
-    pub visibility: bool,
+    /// `file:line` of the `func_enter` lines of the function.
+    ///
+    /// Entries without it, which older versions write, apply to all the locations of the function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// Visibility which is decided by rules if it is not written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<Visibility>,
+    /// Is the function not found in the last debug information?
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    /// Seconds since the UNIX epoch when the function was found in debug information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+}
+
+/// Visibility of each function and each location of it, where rules are applied.
+#[derive(Clone, Debug, Default)]
+pub struct VisibilityMap {
+    functions: HashMap<String, Visibility>,
+    locations: HashMap<String, HashMap<String, Visibility>>,
+    values: ValueFilter,
+}
+
+/// Which value lines in shown calls are shown.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ValueFilter {
+    /// Are all the value lines hidden?
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hide_all: bool,
+    /// Expressions whose value lines are hidden, e.g. `self`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hidden_exprs: Vec<String>,
+    /// Regex which value lines must match to be shown, where `expr = value` is matched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(skip)]
+    regex: Option<Regex>,
 }
 
+/// Syntax of patterns of rules.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternSyntax {
+    /// `*` matches any string and `?` matches any character.
+    #[default]
+    Glob,
+    Regex,
+}
+
+/// Visibility of functions whose names match the pattern.
+#[derive(Clone, Debug, Deserialize, Serialize, TypeName)]
+pub struct VisibilityRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub syntax: PatternSyntax,
+    pub visibility: Visibility,
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+/// Visibility information of the selected profile together with the other profiles.
 #[derive(Clone, Debug, Default, Deserialize, Serialize, TypeName)]
+#[serde(try_from = "VisibilityFile", into = "VisibilityFile")]
 pub struct VisibilityInfo {
+    /// Rules which are evaluated in order for functions whose visibility is not written.
+    pub rules: Vec<VisibilityRule>,
     entries: Vec<VisibilityEntry>,
+    pub values: ValueFilter,
+    /// Name of the selected profile, where `None` is the default one.
+    profile: Option<String>,
+    /// Profiles which are not selected.
+    other_profiles: BTreeMap<Option<String>, Profile>,
 }
 
-impl From<(String, bool)> for VisibilityEntry {
-    fn from((func_name, visibility): (String, bool)) -> Self {
+/// Rules and entries of a profile.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Profile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<VisibilityRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<VisibilityEntry>,
+    #[serde(default, skip_serializing_if = "ValueFilter::shows_all")]
+    values: ValueFilter,
+}
+
+/// Contents of a visibility_info file.
+///
+/// The default profile is written at the top level and the others in `[profiles.<name>]`.
+#[derive(Deserialize, Serialize)]
+struct VisibilityFile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<VisibilityRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<VisibilityEntry>,
+    #[serde(default, skip_serializing_if = "ValueFilter::shows_all")]
+    values: ValueFilter,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl TryFrom<VisibilityValue> for Visibility {
+    type Error = Error;
+    fn try_from(value: VisibilityValue) -> Result<Self> {
+        Ok(match value {
+            VisibilityValue::Bool(true) => Self::Show,
+            VisibilityValue::Bool(false) => Self::HideSubtree,
+            VisibilityValue::Name(name) => match name.as_str() {
+                "show" => Self::Show,
+                "hide_subtree" => Self::HideSubtree,
+                "hide_frame" => Self::HideFrame,
+                "collapse" => Self::Collapse,
+                _ => bail!(
+                    "unknown visibility: {} (available: true, false, show, hide_subtree, hide_frame, collapse)",
+                    name
+                ),
+            },
+        })
+    }
+}
+
+impl From<Visibility> for VisibilityValue {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Show => Self::Bool(true),
+            Visibility::HideSubtree => Self::Bool(false),
+            Visibility::HideFrame => Self::Name("hide_frame".to_string()),
+            Visibility::Collapse => Self::Name("collapse".to_string()),
+        }
+    }
+}
+
+impl Visibility {
+    /// Returns whether the line of the call is shown.
+    pub fn shows_frame(self) -> bool {
+        matches!(self, Self::Show | Self::Collapse)
+    }
+
+    /// Hides the call and all the lines beneath it if its line is shown, and shows it otherwise.
+    pub fn toggle(self) -> Self {
+        if self.shows_frame() {
+            Self::HideSubtree
+        } else {
+            Self::Show
+        }
+    }
+
+    /// Returns the next mode in the order of show, hide subtree, hide frame and collapse.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Show => Self::HideSubtree,
+            Self::HideSubtree => Self::HideFrame,
+            Self::HideFrame => Self::Collapse,
+            Self::Collapse => Self::Show,
+        }
+    }
+
+    /// Returns the name shown in the viewer.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Show => "show",
+            Self::HideSubtree => "hide",
+            Self::HideFrame => "hide frame",
+            Self::Collapse => "collapse",
+        }
+    }
+}
+
+impl TryFrom<VisibilityFile> for VisibilityInfo {
+    type Error = Error;
+    fn try_from(mut visibility_file: VisibilityFile) -> Result<Self> {
+        for rule in visibility_file.rules.iter_mut().chain(
+            visibility_file
+                .profiles
+                .values_mut()
+                .flat_map(|profile| profile.rules.iter_mut()),
+        ) {
+            rule.compile()?;
+        }
+        for values in [&mut visibility_file.values].into_iter().chain(
+            visibility_file
+                .profiles
+                .values_mut()
+                .map(|profile| &mut profile.values),
+        ) {
+            values.compile()?;
+        }
+        Ok(Self {
+            rules: visibility_file.rules,
+            entries: visibility_file.entries,
+            values: visibility_file.values,
+            profile: None,
+            other_profiles: visibility_file
+                .profiles
+                .into_iter()
+                .map(|(name, profile)| (Some(name), profile))
+                .collect(),
+        })
+    }
+}
+
+impl From<VisibilityInfo> for VisibilityFile {
+    fn from(visibility_info: VisibilityInfo) -> Self {
+        let mut profiles = visibility_info.into_profiles();
+        let default = profiles.remove(&None).unwrap_or_default();
+        Self {
+            rules: default.rules,
+            entries: default.entries,
+            values: default.values,
+            profiles: profiles
+                .into_iter()
+                .filter_map(|(name, profile)| Some((name?, profile)))
+                .collect(),
+        }
+    }
+}
+
+impl VisibilityEntry {
+    pub fn new(func_name: String, location: Option<String>) -> Self {
         Self {
             func_name,
-            visibility,
+            location,
+            visibility: None,
+            stale: false,
+            last_seen: Some(now()),
         }
     }
 }
@@ -46,45 +285,259 @@ impl DerefMut for VisibilityInfo {
     }
 }
 
-impl From<VisibilityInfo> for HashMap<String, bool> {
+impl From<VisibilityInfo> for VisibilityMap {
     fn from(visible_info: VisibilityInfo) -> Self {
-        Self::from_iter(
-            visible_info
-                .entries
-                .into_iter()
-                .map(|entry| (entry.func_name, entry.visibility)),
+        let mut visibility_map = Self {
+            values: visible_info.values.clone(),
+            ..Default::default()
+        };
+        for entry in visible_info.entries.iter() {
+            let visibility = visible_info.visibility_of_entry(entry);
+            match &entry.location {
+                Some(location) => {
+                    visibility_map
+                        .locations
+                        .entry(entry.func_name.clone())
+                        .or_default()
+                        .insert(location.clone(), visibility);
+                    visibility_map
+                        .functions
+                        .entry(entry.func_name.clone())
+                        .or_insert_with(|| visible_info.visibility(&entry.func_name));
+                }
+                None => {
+                    visibility_map
+                        .functions
+                        .insert(entry.func_name.clone(), visibility);
+                }
+            }
+        }
+        visibility_map
+    }
+}
+
+/// Functions which are shown at all the locations.
+impl FromIterator<(String, Visibility)> for VisibilityMap {
+    fn from_iter<I: IntoIterator<Item = (String, Visibility)>>(functions: I) -> Self {
+        Self {
+            functions: functions.into_iter().collect(),
+            locations: HashMap::new(),
+            values: ValueFilter::default(),
+        }
+    }
+}
+
+impl VisibilityMap {
+    /// Returns the visibility of a call of the function at the location,
+    /// where that of the location takes precedence over that of the function.
+    pub fn get(&self, func_name: &str, location: &str) -> Option<Visibility> {
+        self.locations
+            .get(func_name)
+            .and_then(|locations| locations.get(location))
+            .or_else(|| self.functions.get(func_name))
+            .copied()
+    }
+
+    /// Returns the visibility of the `func_enter` line.
+    pub fn get_by_line(&self, line: &str) -> Option<Visibility> {
+        self.get(
+            debug_info::func_name(line)?,
+            debug_info::location(line).unwrap_or_default(),
         )
     }
+
+    pub fn contains_function(&self, func_name: &str) -> bool {
+        self.functions.contains_key(func_name)
+    }
+
+    /// Sets the visibility of the function at the locations without their own one.
+    pub fn insert_function(&mut self, func_name: String, visibility: Visibility) {
+        self.functions.insert(func_name, visibility);
+    }
+
+    /// Returns whether the value line is shown if the call which it is written in is shown.
+    pub fn shows_value(&self, line: &str) -> bool {
+        self.values.is_match(debug_info::body(line))
+    }
 }
-impl From<Vec<VisibilityEntry>> for VisibilityInfo {
-    fn from(entries: Vec<VisibilityEntry>) -> Self {
-        Self { entries }
+
+/// Filters are compared by what is written.
+impl PartialEq for ValueFilter {
+    fn eq(&self, rhs: &Self) -> bool {
+        (self.hide_all, &self.hidden_exprs, &self.pattern)
+            == (rhs.hide_all, &rhs.hidden_exprs, &rhs.pattern)
     }
 }
 
-impl From<HashMap<String, bool>> for VisibilityInfo {
-    fn from(hash_map: HashMap<String, bool>) -> Self {
-        Self {
-            entries: hash_map
-                .into_iter()
-                .map(|(func_name, visibility)| VisibilityEntry {
-                    func_name,
-                    visibility,
-                })
-                .collect(),
-        }
+impl ValueFilter {
+    /// Returns whether all the value lines are shown.
+    pub fn shows_all(&self) -> bool {
+        !self.hide_all && self.hidden_exprs.is_empty() && self.pattern.is_none()
+    }
+
+    /// Sets the pattern, where `None` removes it.
+    pub fn set_pattern(&mut self, pattern: Option<String>) -> Result<()> {
+        let old_pattern = std::mem::replace(&mut self.pattern, pattern);
+        self.compile().inspect_err(|_| {
+            self.pattern = old_pattern;
+        })
+    }
+
+    /// Compiles the pattern, which must be done before matching.
+    fn compile(&mut self) -> Result<()> {
+        self.regex = match &self.pattern {
+            Some(pattern) => {
+                Some(Regex::new(pattern).with_context(|| format!("invalid pattern: {}", pattern))?)
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Returns whether the value line whose body is `expr = value` is shown.
+    pub fn is_match(&self, body: &str) -> bool {
+        let (expr, _) = debug_info::split_value(body);
+        !self.hide_all
+            && !self
+                .hidden_exprs
+                .iter()
+                .any(|hidden_expr| hidden_expr == expr)
+            && self.regex.as_ref().is_none_or(|regex| regex.is_match(body))
     }
 }
 
 impl PartialOrd for VisibilityEntry {
     fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
-        self.func_name.partial_cmp(&rhs.func_name)
+        Some(self.cmp(rhs))
     }
 }
 
+/// Entries are sorted by names, where the entry for all the locations precedes the others.
 impl Ord for VisibilityEntry {
     fn cmp(&self, rhs: &Self) -> Ordering {
-        self.func_name.cmp(&rhs.func_name)
+        (&self.func_name, &self.location).cmp(&(&rhs.func_name, &rhs.location))
+    }
+}
+
+/// Rules are compared by what is written.
+impl PartialEq for VisibilityRule {
+    fn eq(&self, rhs: &Self) -> bool {
+        (&self.pattern, self.syntax, self.visibility) == (&rhs.pattern, rhs.syntax, rhs.visibility)
+    }
+}
+
+impl Profile {
+    /// Merges changes made in `theirs` since `base` into this, where changes of this win.
+    ///
+    /// Rules and entries which are not changed from `base` follow `theirs`, including removal,
+    /// and so do entries added in both whose visibility is set only in `theirs`.
+    fn merge(self, base: Self, theirs: Self) -> Self {
+        let rules = if self.rules == base.rules {
+            theirs.rules
+        } else {
+            self.rules
+        };
+        let values = if self.values == base.values {
+            theirs.values
+        } else {
+            self.values
+        };
+        let key = |entry: &VisibilityEntry| (entry.func_name.clone(), entry.location.clone());
+        let base_entries = base
+            .entries
+            .into_iter()
+            .map(|entry| (key(&entry), entry))
+            .collect::<HashMap<_, _>>();
+        let mut their_entries = theirs
+            .entries
+            .into_iter()
+            .map(|entry| (key(&entry), entry))
+            .collect::<HashMap<_, _>>();
+        let mut entries = Vec::new();
+        for mut entry in self.entries {
+            let their_entry = their_entries.remove(&key(&entry));
+            match (base_entries.get(&key(&entry)), their_entry) {
+                (Some(base_entry), their_entry) if base_entry.visibility == entry.visibility => {
+                    if let Some(their_entry) = their_entry {
+                        entry.visibility = their_entry.visibility;
+                        entries.push(entry);
+                    }
+                }
+                // Both have added the entry, e.g. of a function new to debug_info,
+                // where this has not set its visibility.
+                (None, Some(their_entry)) if entry.visibility.is_none() => {
+                    entry.visibility = their_entry.visibility;
+                    entries.push(entry);
+                }
+                _ => entries.push(entry),
+            }
+        }
+        // Entries removed in this are not added again.
+        entries.extend(
+            their_entries
+                .into_iter()
+                .filter(|(key, _)| !base_entries.contains_key(key))
+                .map(|(_, entry)| entry),
+        );
+        entries.sort();
+        Self {
+            rules,
+            entries,
+            values,
+        }
+    }
+}
+
+impl VisibilityRule {
+    pub fn try_new(pattern: String, syntax: PatternSyntax, visibility: Visibility) -> Result<Self> {
+        let mut rule = Self {
+            pattern,
+            syntax,
+            visibility,
+            regex: None,
+        };
+        rule.compile()?;
+        Ok(rule)
+    }
+
+    /// Compiles the pattern, which must be done before matching.
+    fn compile(&mut self) -> Result<()> {
+        let regex = match self.syntax {
+            PatternSyntax::Glob => {
+                let mut regex = String::from("^");
+                for c in self.pattern.chars() {
+                    match c {
+                        '*' => regex.push_str(".*"),
+                        '?' => regex.push('.'),
+                        c => regex.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                regex.push('$');
+                regex
+            }
+            PatternSyntax::Regex => self.pattern.clone(),
+        };
+        self.regex =
+            Some(Regex::new(&regex).with_context(|| format!("invalid pattern: {}", self.pattern))?);
+        Ok(())
+    }
+
+    pub fn is_match(&self, func_name: &str) -> bool {
+        self.regex
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(func_name))
+    }
+
+    /// Switches the syntax between glob and regex.
+    pub fn toggle_syntax(&mut self) -> Result<()> {
+        let syntax = self.syntax;
+        self.syntax = match syntax {
+            PatternSyntax::Glob => PatternSyntax::Regex,
+            PatternSyntax::Regex => PatternSyntax::Glob,
+        };
+        self.compile().inspect_err(|_| {
+            self.syntax = syntax;
+        })
     }
 }
 
@@ -107,13 +560,21 @@ impl VisibilityInfo {
         }
     }
 
+    /// Reads visibility information if the file exists, which is not required to be writable.
+    pub fn read_if_exists(file: &Path) -> Result<Self> {
+        if file.is_file() {
+            VisibilityInfo::read_toml_file(file)
+        } else {
+            Ok(VisibilityInfo::default())
+        }
+    }
+
     #[methodlog]
     pub fn write_toml_file(&self, file: &Path) -> Result<()> {
-        fs::write(
+        write_file_atomically(
             file,
-            toml::to_string(&self).context("failed to change toml into string")?,
+            &toml::to_string(&self).context("failed to change toml into string")?,
         )
-        .with_context(|| format!("failed to write toml into {}", file.display()))
     }
 
     pub fn read_toml_file(file: &Path) -> Result<Self> {
@@ -121,34 +582,735 @@ impl VisibilityInfo {
             &fs::read_to_string(file)
                 .with_context(|| format!("failed to read {}", file.display()))?,
         )
-        .map_err(Into::into)
-    }
-
-    pub fn update_by_debug_info(self, debug_info: &str) -> Result<Self> {
-        let mut old_visibility_info = HashMap::from(self);
-        let mut new_visibility_info = HashMap::with_capacity(old_visibility_info.len());
-        for func_name_result in debug_info.lines().enumerate().filter_map(|(i_line, line)| {
-            if line.starts_with("[DEBUG:func_enter") {
-                Some(
-                    line.split_ascii_whitespace()
-                        .last()
-                        .map(str::to_string)
-                        .with_context(|| {
-                            format!("the {}-th line of debug_info is invalid format", i_line + 1)
-                        }),
-                )
+        .with_context(|| format!("failed to parse {}", file.display()))
+    }
+
+    /// Returns all the profiles including the selected one.
+    fn into_profiles(self) -> BTreeMap<Option<String>, Profile> {
+        let mut profiles = self.other_profiles;
+        profiles.insert(
+            self.profile,
+            Profile {
+                rules: self.rules,
+                entries: self.entries,
+                values: self.values,
+            },
+        );
+        profiles
+    }
+
+    /// Merges changes made in `theirs`, e.g. the file written by another viewer,
+    /// since `base` into this, where changes of this win.
+    ///
+    /// The selected profile is kept, and its entries must be updated by debug_info after this.
+    pub fn merge(self, base: Self, theirs: Self) -> Self {
+        let profile = self.profile.clone();
+        let mut profiles = self.into_profiles();
+        let mut base_profiles = base.into_profiles();
+        for (name, their_profile) in theirs.into_profiles() {
+            let base_profile = base_profiles.remove(&name).unwrap_or_default();
+            let merged = profiles
+                .remove(&name)
+                .unwrap_or_default()
+                .merge(base_profile, their_profile);
+            profiles.insert(name, merged);
+        }
+        let selected = profiles.remove(&profile).unwrap_or_default();
+        Self {
+            rules: selected.rules,
+            entries: selected.entries,
+            values: selected.values,
+            profile,
+            other_profiles: profiles,
+        }
+    }
+
+    /// Returns the name of the selected profile, where `None` is the default one.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Returns the names of all the profiles, from the default one.
+    pub fn profiles(&self) -> Vec<Option<&str>> {
+        let mut profiles = self
+            .other_profiles
+            .keys()
+            .map(Option::as_deref)
+            .chain([self.profile()])
+            .collect::<Vec<_>>();
+        profiles.sort();
+        profiles.dedup();
+        profiles
+    }
+
+    /// Selects the profile, which is created if it does not exist.
+    ///
+    /// Entries of the profile must be updated by debug_info after this.
+    pub fn select_profile(&mut self, profile: Option<&str>) {
+        if self.profile() == profile {
+            return;
+        }
+        let selected = Profile {
+            rules: std::mem::take(&mut self.rules),
+            entries: std::mem::take(&mut self.entries),
+            values: std::mem::take(&mut self.values),
+        };
+        self.other_profiles.insert(self.profile.take(), selected);
+        self.profile = profile.map(str::to_string);
+        let profile = self
+            .other_profiles
+            .remove(&self.profile)
+            .unwrap_or_default();
+        self.rules = profile.rules;
+        self.entries = profile.entries;
+        self.values = profile.values;
+    }
+
+    /// Selects the profile, which must exist unless it is the default one.
+    pub fn with_profile(mut self, profile: Option<&str>) -> Result<Self> {
+        if !self.profiles().contains(&profile) {
+            bail!(
+                "unknown profile: {} (available: {})",
+                profile.unwrap_or_default(),
+                self.profiles()
+                    .into_iter()
+                    .map(|profile| profile.unwrap_or("(default)"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        self.select_profile(profile);
+        Ok(self)
+    }
+
+    /// Returns the rule which decides the visibility of the function if it is not written.
+    pub fn rule_of(&self, func_name: &str) -> Option<&VisibilityRule> {
+        self.rules.iter().find(|rule| rule.is_match(func_name))
+    }
+
+    /// Returns the index of the entry of the function at the location,
+    /// where `None` is the entry for all the locations.
+    pub fn position(&self, func_name: &str, location: Option<&str>) -> Result<usize, usize> {
+        self.entries.binary_search_by(|entry| {
+            (entry.func_name.as_str(), entry.location.as_deref()).cmp(&(func_name, location))
+        })
+    }
+
+    /// Returns the rule which decides the visibility of the entry if any.
+    pub fn rule_of_entry(&self, entry: &VisibilityEntry) -> Option<&VisibilityRule> {
+        if entry.visibility.is_some() {
+            return None;
+        }
+        match (&entry.location, self.position(&entry.func_name, None)) {
+            (Some(_), Ok(idx)) => self.rule_of_entry(&self.entries[idx]),
+            _ => self.rule_of(&entry.func_name),
+        }
+    }
+
+    /// Returns the visibility of the entry.
+    ///
+    /// An entry of a location without visibility follows the entry for all the locations,
+    /// and then rules, and it is shown if no rule matches it.
+    pub fn visibility_of_entry(&self, entry: &VisibilityEntry) -> Visibility {
+        match (entry.visibility, &entry.location) {
+            (Some(visibility), _) => visibility,
+            (None, Some(_)) => self.visibility(&entry.func_name),
+            (None, None) => self
+                .rule_of(&entry.func_name)
+                .map_or(Visibility::Show, |rule| rule.visibility),
+        }
+    }
+
+    /// Returns the visibility of the function at locations without their own visibility,
+    /// where exact entries take precedence over rules.
+    pub fn visibility(&self, func_name: &str) -> Visibility {
+        match self.position(func_name, None) {
+            Ok(idx) => self.visibility_of_entry(&self.entries[idx]),
+            Err(_) => self
+                .rule_of(func_name)
+                .map_or(Visibility::Show, |rule| rule.visibility),
+        }
+    }
+
+    /// Updates entries by the functions and their locations in debug_info.
+    ///
+    /// Entries are marked in the same way as [`mark_by_debug_info`](Self::mark_by_debug_info),
+    /// and new functions get entries in the same way as [`add_function`](Self::add_function).
+    pub fn update_by_debug_info(mut self, debug_info: &str) -> Result<Self> {
+        let func_locations = func_locations(debug_info)?;
+        self.mark_found(&func_locations);
+        for (func_name, locations) in func_locations {
+            self.add_function(func_name, &locations.into_iter().collect::<Vec<_>>());
+        }
+        Ok(self)
+    }
+
+    /// Marks entries of functions which are not found in debug_info as stale ones
+    /// without adding entries of new functions, e.g. before pruning.
+    ///
+    /// Entries for all the locations are kept as long as the functions are found.
+    pub fn mark_by_debug_info(mut self, debug_info: &str) -> Result<Self> {
+        self.mark_found(&func_locations(debug_info)?);
+        Ok(self)
+    }
+
+    fn mark_found(&mut self, func_locations: &HashMap<&str, BTreeSet<&str>>) {
+        self.entries.sort();
+        let now = now();
+        for entry in self.entries.iter_mut() {
+            let is_found = match (
+                func_locations.get(entry.func_name.as_str()),
+                &entry.location,
+            ) {
+                (Some(locations), Some(location)) => locations.contains(location.as_str()),
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if is_found {
+                entry.stale = false;
+                entry.last_seen = Some(now);
             } else {
-                None
+                entry.stale = true;
+            }
+        }
+    }
+
+    /// Adds entries of the functions of the calls from `first_call`,
+    /// e.g. those in lines appended to debug_info.
+    ///
+    /// Returns whether entries are added or found again.
+    pub fn extend_by_call_tree(&mut self, call_tree: &CallTree, first_call: usize) -> bool {
+        let mut changed = false;
+        let mut func_names = HashSet::new();
+        for call in call_tree.calls.iter().skip(first_call) {
+            for location in [None, Some(call.location.as_str())] {
+                if let Ok(idx) = self.position(&call.func_name, location) {
+                    let entry = &mut self.entries[idx];
+                    if entry.stale {
+                        entry.stale = false;
+                        entry.last_seen = Some(now());
+                        changed = true;
+                    }
+                }
+            }
+            func_names.insert(call.func_name.as_str());
+        }
+        for func_name in func_names {
+            let locations = call_tree
+                .func_locations
+                .get(func_name)
+                .map_or(Vec::new(), |locations| {
+                    locations.iter().map(String::as_str).collect()
+                });
+            changed |= self.add_function(func_name, &locations);
+        }
+        changed
+    }
+
+    /// Adds entries of the function found at the locations if they do not exist.
+    ///
+    /// A function found at more than one location gets an entry for each location,
+    /// and a function found at one location gets an entry for all the locations.
+    /// Returns whether entries are added.
+    fn add_function(&mut self, func_name: &str, locations: &[&str]) -> bool {
+        match locations {
+            [location] => {
+                if self.position(func_name, None).is_ok()
+                    || self.position(func_name, Some(location)).is_ok()
+                {
+                    return false;
+                }
+                let idx = self.position(func_name, None).unwrap_err();
+                self.entries
+                    .insert(idx, VisibilityEntry::new(func_name.to_string(), None));
+                true
+            }
+            _ => self.add_locations(func_name, locations),
+        }
+    }
+
+    /// Adds an entry for each location of the function which does not have its own one,
+    /// e.g. to give the locations different visibility.
+    ///
+    /// Returns whether entries are added.
+    pub fn add_locations(&mut self, func_name: &str, locations: &[&str]) -> bool {
+        let mut added = false;
+        for location in locations {
+            if let Err(idx) = self.position(func_name, Some(location)) {
+                self.entries.insert(
+                    idx,
+                    VisibilityEntry::new(func_name.to_string(), Some(location.to_string())),
+                );
+                added = true;
             }
-        }) {
-            let func_name = func_name_result?;
-            let entry = old_visibility_info
-                .remove_entry(&func_name)
-                .map_or((func_name, true), |entry| entry);
-            new_visibility_info.insert(entry.0, entry.1);
-        }
-        let mut new_visibility_info = VisibilityInfo::from(new_visibility_info);
-        new_visibility_info.sort();
-        Ok(new_visibility_info)
+        }
+        added
+    }
+
+    /// Removes stale entries which were last seen before the time, or all of them if no time is given.
+    ///
+    /// Returns the removed entries.
+    pub fn prune(&mut self, seen_before: Option<u64>) -> Vec<VisibilityEntry> {
+        let (pruned, entries) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| {
+                entry.stale
+                    && seen_before.is_none_or(|seen_before| {
+                        entry
+                            .last_seen
+                            .is_none_or(|last_seen| last_seen < seen_before)
+                    })
+            });
+        self.entries = entries;
+        pruned
+    }
+}
+
+/// Returns the locations of each function entered in debug_info.
+fn func_locations(debug_info: &str) -> Result<HashMap<&str, BTreeSet<&str>>> {
+    let mut func_locations = HashMap::<&str, BTreeSet<&str>>::new();
+    for (i_line, line) in debug_info.lines().enumerate() {
+        if line.starts_with("[DEBUG:func_enter") {
+            func_locations
+                .entry(line.split_ascii_whitespace().last().with_context(|| {
+                    format!("the {}-th line of debug_info is invalid format", i_line + 1)
+                })?)
+                .or_default()
+                .insert(debug_info::location(line).unwrap_or_default());
+        }
+    }
+    Ok(func_locations)
+}
+
+/// Writes the contents into a temporary file next to the file and renames it,
+/// so that the file is not broken even if writing fails halfway.
+///
+/// The temporary file is removed if it cannot be written or renamed.
+pub fn write_file_atomically(file: &Path, contents: &str) -> Result<()> {
+    write_atomically(file, |writer| writer.write_all(contents.as_bytes()))
+}
+
+/// Writes a temporary file by `write` and renames it to the file.
+fn write_atomically(file: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> Result<()> {
+    let mut tmp_file = file.as_os_str().to_owned();
+    tmp_file.push(format!(".{}.tmp", std::process::id()));
+    let tmp_file = PathBuf::from(tmp_file);
+    let result = File::create(&tmp_file)
+        .and_then(|mut writer| {
+            write(&mut writer)?;
+            writer.sync_all()
+        })
+        .with_context(|| format!("failed to write into {}", tmp_file.display()))
+        .and_then(|_| {
+            fs::rename(&tmp_file, file).with_context(|| {
+                format!(
+                    "failed to rename {} to {}",
+                    tmp_file.display(),
+                    file.display()
+                )
+            })
+        });
+    if result.is_err() {
+        // The error of writing matters more than that of removing.
+        let _ = fs::remove_file(&tmp_file);
+    }
+    result
+}
+
+/// Returns the time when the file was modified last, which is `None` if it does not exist.
+pub fn modified_time(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Returns seconds since the UNIX epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visibilities(visibility_info: &VisibilityInfo) -> Vec<Option<Visibility>> {
+        visibility_info
+            .iter()
+            .map(|entry| entry.visibility)
+            .collect()
+    }
+
+    #[test]
+    fn read_and_write_old_boolean_file() {
+        let visibility_info = toml::from_str::<VisibilityInfo>(
+            r#"
+            [[entries]]
+            func_name = "bar"
+            visibility = false
+
+            [[entries]]
+            func_name = "foo"
+            visibility = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            visibilities(&visibility_info),
+            [Some(Visibility::HideSubtree), Some(Visibility::Show)]
+        );
+        let written = toml::to_string(&visibility_info).unwrap();
+        assert!(written.contains("visibility = false"));
+        assert!(written.contains("visibility = true"));
+        assert!(!written.contains("location"));
+        let read = toml::from_str::<VisibilityInfo>(&written).unwrap();
+        assert_eq!(*read, *visibility_info);
+    }
+
+    #[test]
+    fn read_and_write_visibility_names() {
+        let visibility_info = toml::from_str::<VisibilityInfo>(
+            r#"
+            [[rules]]
+            pattern = "*::fmt"
+            visibility = "hide_frame"
+
+            [[entries]]
+            func_name = "a"
+            visibility = "show"
+
+            [[entries]]
+            func_name = "b"
+            visibility = "hide_subtree"
+
+            [[entries]]
+            func_name = "c"
+            visibility = "hide_frame"
+
+            [[entries]]
+            func_name = "d"
+            visibility = "collapse"
+
+            [[entries]]
+            func_name = "e"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(visibility_info.rules[0].visibility, Visibility::HideFrame);
+        assert_eq!(
+            visibilities(&visibility_info),
+            [
+                Some(Visibility::Show),
+                Some(Visibility::HideSubtree),
+                Some(Visibility::HideFrame),
+                Some(Visibility::Collapse),
+                None,
+            ]
+        );
+        // Showing and hiding are written as booleans so that older versions can read them.
+        let written = toml::to_string(&visibility_info).unwrap();
+        assert!(written.contains("visibility = true"));
+        assert!(written.contains("visibility = false"));
+        assert!(written.contains(r#"visibility = "hide_frame""#));
+        assert!(written.contains(r#"visibility = "collapse""#));
+        let read = toml::from_str::<VisibilityInfo>(&written).unwrap();
+        assert_eq!(*read, *visibility_info);
+        assert_eq!(read.rules, visibility_info.rules);
+    }
+
+    fn profile(rules: &[&str], entries: &[(&str, Option<Visibility>)]) -> Profile {
+        Profile {
+            rules: rules
+                .iter()
+                .map(|pattern| {
+                    VisibilityRule::try_new(
+                        pattern.to_string(),
+                        PatternSyntax::Glob,
+                        Visibility::HideSubtree,
+                    )
+                    .unwrap()
+                })
+                .collect(),
+            entries: entries
+                .iter()
+                .map(|(func_name, visibility)| VisibilityEntry {
+                    visibility: *visibility,
+                    ..VisibilityEntry::new(func_name.to_string(), None)
+                })
+                .collect(),
+            values: ValueFilter::default(),
+        }
+    }
+
+    fn entries(profile: &Profile) -> Vec<(&str, Option<Visibility>)> {
+        profile
+            .entries
+            .iter()
+            .map(|entry| (entry.func_name.as_str(), entry.visibility))
+            .collect()
+    }
+
+    #[test]
+    fn select_and_write_profiles() {
+        let mut visibility_info = toml::from_str::<VisibilityInfo>(
+            r#"
+            [[entries]]
+            func_name = "a"
+            visibility = false
+
+            [profiles.quiet]
+            [[profiles.quiet.entries]]
+            func_name = "a"
+            visibility = "collapse"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(visibility_info.profiles(), [None, Some("quiet")]);
+        assert!(visibility_info.clone().with_profile(Some("loud")).is_err());
+        visibility_info.select_profile(Some("quiet"));
+        assert_eq!(visibilities(&visibility_info), [Some(Visibility::Collapse)]);
+        // A new profile is created empty, and the default one is written at the top level.
+        visibility_info.select_profile(Some("new"));
+        assert!(visibility_info.is_empty());
+        let written = toml::to_string(&visibility_info).unwrap();
+        let mut read = toml::from_str::<VisibilityInfo>(&written).unwrap();
+        assert_eq!(visibilities(&read), [Some(Visibility::HideSubtree)]);
+        assert_eq!(read.profiles(), [None, Some("new"), Some("quiet")]);
+        read.select_profile(Some("quiet"));
+        assert_eq!(visibilities(&read), [Some(Visibility::Collapse)]);
+    }
+
+    #[test]
+    fn merge_changes_of_both_sides() {
+        let base = profile(
+            &["a*"],
+            &[("a", None), ("b", None), ("c", None), ("d", None)],
+        );
+        // This hides `a`, removes `d` and adds `f` and `g` found in debug_info, showing `g`.
+        let ours = profile(
+            &["a*"],
+            &[
+                ("a", Some(Visibility::HideSubtree)),
+                ("b", None),
+                ("c", None),
+                ("f", None),
+                ("g", Some(Visibility::Show)),
+            ],
+        );
+        // The other hides `a` and `b`, removes `c`, adds `e` and adds `f` and `g` hiding them.
+        let theirs = profile(
+            &["b*"],
+            &[
+                ("a", Some(Visibility::Collapse)),
+                ("b", Some(Visibility::HideSubtree)),
+                ("d", None),
+                ("e", Some(Visibility::HideFrame)),
+                ("f", Some(Visibility::HideSubtree)),
+                ("g", Some(Visibility::HideSubtree)),
+            ],
+        );
+        let merged = ours.merge(base, theirs);
+        assert_eq!(
+            merged
+                .rules
+                .iter()
+                .map(|rule| rule.pattern.as_str())
+                .collect::<Vec<_>>(),
+            ["b*"]
+        );
+        assert_eq!(
+            entries(&merged),
+            [
+                ("a", Some(Visibility::HideSubtree)),
+                ("b", Some(Visibility::HideSubtree)),
+                ("e", Some(Visibility::HideFrame)),
+                ("f", Some(Visibility::HideSubtree)),
+                ("g", Some(Visibility::Show)),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_changed_rules() {
+        let base = profile(&["a*"], &[]);
+        let ours = profile(&["a*", "c*"], &[]);
+        let theirs = profile(&["b*"], &[]);
+        assert_eq!(
+            ours.merge(base, theirs)
+                .rules
+                .iter()
+                .map(|rule| rule.pattern.as_str())
+                .collect::<Vec<_>>(),
+            ["a*", "c*"]
+        );
+    }
+
+    #[test]
+    fn toggle_hides_shown_frames() {
+        assert_eq!(Visibility::Show.toggle(), Visibility::HideSubtree);
+        assert_eq!(Visibility::Collapse.toggle(), Visibility::HideSubtree);
+        assert_eq!(Visibility::HideSubtree.toggle(), Visibility::Show);
+        assert_eq!(Visibility::HideFrame.toggle(), Visibility::Show);
+    }
+
+    #[test]
+    fn unknown_visibility_is_error() {
+        assert!(toml::from_str::<VisibilityInfo>(
+            r#"
+            [[entries]]
+            func_name = "a"
+            visibility = "hidden"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn add_entries_of_locations_only_if_needed() {
+        let debug_info = "\
+[DEBUG:func_enter(src/main.rs:3)] main
+[DEBUG:func_enter(src/a.rs:10)] new
+[DEBUG:func_exit(src/a.rs:10)] new
+[DEBUG:func_enter(src/b.rs:20)] new
+[DEBUG:func_exit(src/b.rs:20)] new
+[DEBUG:func_enter(src/c.rs:30)] old
+[DEBUG:func_exit(src/c.rs:30)] old
+[DEBUG:func_exit(src/main.rs:3)] main
+";
+        let visibility_info = toml::from_str::<VisibilityInfo>(
+            r#"
+            [[entries]]
+            func_name = "new"
+            visibility = false
+
+            [[entries]]
+            func_name = "old"
+            visibility = false
+            "#,
+        )
+        .unwrap()
+        .update_by_debug_info(debug_info)
+        .unwrap();
+        let entries = |visibility_info: &VisibilityInfo| {
+            visibility_info
+                .iter()
+                .map(|entry| (entry.func_name.clone(), entry.location.clone()))
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            ("main".to_string(), None),
+            ("new".to_string(), None),
+            ("new".to_string(), Some("src/a.rs:10".to_string())),
+            ("new".to_string(), Some("src/b.rs:20".to_string())),
+            ("old".to_string(), None),
+        ];
+        assert_eq!(entries(&visibility_info), expected);
+        assert!(visibility_info.iter().all(|entry| !entry.stale));
+        let mut visibility_info = visibility_info.update_by_debug_info(debug_info).unwrap();
+        assert_eq!(entries(&visibility_info), expected);
+        assert!(visibility_info.add_locations("old", &["src/c.rs:30"]));
+        assert!(!visibility_info.add_locations("old", &["src/c.rs:30"]));
+        assert_eq!(visibility_info.position("old", Some("src/c.rs:30")), Ok(5));
+    }
+
+    #[test]
+    fn write_file_atomically_removes_temporary_file_on_failure() {
+        let dir =
+            std::env::temp_dir().join(format!("proctrack-test-{}-atomic", std::process::id()));
+        let file = dir.join("vis.toml");
+        fs::create_dir_all(&file).unwrap();
+        fs::write(file.join("other"), "").unwrap();
+        assert!(write_file_atomically(&file, "a").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&file).unwrap();
+        // e.g. the disk is full.
+        assert!(write_atomically(&file, |writer| {
+            writer.write_all(b"partial")?;
+            Err(io::Error::other("no space left"))
+        })
+        .is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        write_file_atomically(&file, "b").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "b");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filter_values_by_exprs_and_pattern() {
+        let mut values = ValueFilter::default();
+        assert!(values.shows_all());
+        assert!(values.is_match("self = Foo"));
+        values.hidden_exprs.push("self".to_string());
+        assert!(!values.shows_all());
+        assert!(!values.is_match("self = Foo"));
+        assert!(values.is_match("self.x = 3"));
+        values.set_pattern(Some("= [0-9]+$".to_string())).unwrap();
+        assert!(values.is_match("x = 3"));
+        assert!(!values.is_match("name = \"a\""));
+        assert!(!values.is_match("self = 3"));
+        values.hide_all = true;
+        assert!(!values.is_match("x = 3"));
+    }
+
+    #[test]
+    fn invalid_pattern_keeps_old_one() {
+        let mut values = ValueFilter::default();
+        values.set_pattern(Some("^x ".to_string())).unwrap();
+        assert!(values.set_pattern(Some("(".to_string())).is_err());
+        assert_eq!(values.pattern.as_deref(), Some("^x "));
+        assert!(values.is_match("x = 3"));
+        assert!(!values.is_match("y = 3"));
+        values.set_pattern(None).unwrap();
+        assert!(values.shows_all());
+        assert!(values.is_match("y = 3"));
+    }
+
+    #[test]
+    fn read_and_write_value_filter() {
+        let visibility_info = toml::from_str::<VisibilityInfo>(
+            r#"
+            [values]
+            hidden_exprs = ["self"]
+            pattern = "^x "
+            "#,
+        )
+        .unwrap();
+        assert!(visibility_info.values.is_match("x = 3"));
+        assert!(!visibility_info.values.is_match("y = 3"));
+        let written = toml::to_string(&visibility_info).unwrap();
+        let read = toml::from_str::<VisibilityInfo>(&written).unwrap();
+        assert_eq!(read.values.hidden_exprs, ["self"]);
+        assert!(!read.values.is_match("y = 3"));
+        assert!(!toml::to_string(&VisibilityInfo::default())
+            .unwrap()
+            .contains("values"));
+        assert!(toml::from_str::<VisibilityInfo>("[values]\npattern = \"(\"\n").is_err());
+    }
+
+    #[test]
+    fn prune_functions_not_found_without_adding_new_ones() {
+        let mut visibility_info = toml::from_str::<VisibilityInfo>(
+            r#"
+            [[entries]]
+            func_name = "foo"
+            visibility = false
+
+            [[entries]]
+            func_name = "gone"
+            visibility = false
+            "#,
+        )
+        .unwrap()
+        .mark_by_debug_info(
+            "[DEBUG:func_enter(src/main.rs:1)] main\n[DEBUG:func_enter(src/lib.rs:5)] foo\n",
+        )
+        .unwrap();
+        let pruned = visibility_info.prune(None);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].func_name, "gone");
+        assert_eq!(visibility_info.len(), 1);
+        assert_eq!(visibility_info[0].func_name, "foo");
+        assert!(visibility_info[0].last_seen.is_some());
     }
 }